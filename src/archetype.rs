@@ -1,46 +1,94 @@
-use crate::{component::ComponentSet, entity::Entity};
+use std::{any::TypeId, collections::HashMap};
+
+use crate::{
+    component::{ComponentId, ComponentSet, RequiredComponent},
+    entity::{Entity, EntityId},
+};
+
+/// The index of an archetype in its [`World`](crate::World).
+pub type ArchetypeId = usize;
+
+/// The archetype an entity moves to when a bundle of components is added
+/// to it, along with the required components inserted on the way.
+#[derive(Clone, Debug)]
+pub(crate) struct AddEdge {
+    pub(crate) target: ArchetypeId,
+    pub(crate) required: Vec<RequiredComponent>,
+}
+
+/// What is added to an entity when it moves along an add edge.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Bundle {
+    /// A `ComponentTuple`, by the `TypeId` of the tuple.
+    Tuple(TypeId),
+    /// A single component, by its id.
+    Component(ComponentId),
+}
+
+/// The archetypes reached from an archetype by adding a bundle of
+/// components, or by removing a component, keyed by the id of the
+/// component.
+#[derive(Default, Debug)]
+struct Edges {
+    add: HashMap<Bundle, AddEdge>,
+    remove: HashMap<ComponentId, ArchetypeId>,
+}
 
 #[derive(Debug)]
 pub struct Archetype {
+    id: ArchetypeId,
     entities: Vec<Entity>,
+    // The index of each entity in `entities`.
+    rows: HashMap<EntityId, usize>,
     components: ComponentSet,
+    edges: Edges,
 }
 
 impl Archetype {
-    pub fn new(components: ComponentSet) -> Self {
+    pub fn new(id: ArchetypeId, components: ComponentSet) -> Self {
         Self {
+            id,
             entities: Vec::new(),
+            rows: HashMap::new(),
             components,
+            edges: Edges::default(),
         }
     }
 
+    pub fn id(&self) -> ArchetypeId {
+        self.id
+    }
+
     pub fn push(&mut self, entity: Entity) {
+        self.rows.insert(entity.id(), self.entities.len());
         self.entities.push(entity);
     }
 
     pub fn pop(&mut self) -> Option<Entity> {
-        self.entities.pop()
+        let entity = self.entities.pop()?;
+        self.rows.remove(&entity.id());
+        Some(entity)
     }
 
+    /// Removes the entity from the archetype, moving the last entity into
+    /// its place. Returns `false` if the archetype didn't contain it.
     pub fn remove(&mut self, entity: Entity) -> bool {
-        let index = self
-            .entities
-            .iter()
-            .enumerate()
-            .find(|(_, other)| entity.id() == other.id())
-            .map(|(index, _)| index);
-
-        match index {
-            Some(index) => {
-                self.entities.remove(index);
-                true
-            }
-            None => false,
+        let index = match self.rows.remove(&entity.id()) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        self.entities.swap_remove(index);
+
+        if let Some(moved) = self.entities.get(index) {
+            self.rows.insert(moved.id(), index);
         }
+
+        true
     }
 
     pub fn contains(&self, entity: Entity) -> bool {
-        self.entities.contains(&entity)
+        self.rows.contains_key(&entity.id())
     }
 
     pub fn entities(&self) -> &[Entity] {
@@ -54,4 +102,128 @@ impl Archetype {
     pub fn entity_iter(&self) -> impl Iterator<Item = &'_ Entity> {
         self.entities.iter()
     }
+
+    pub(crate) fn add_edge(&self, bundle: Bundle) -> Option<&AddEdge> {
+        self.edges.add.get(&bundle)
+    }
+
+    pub(crate) fn set_add_edge(&mut self, bundle: Bundle, edge: AddEdge) {
+        self.edges.add.insert(bundle, edge);
+    }
+
+    pub(crate) fn remove_edge(&self, component: ComponentId) -> Option<ArchetypeId> {
+        self.edges.remove.get(&component).copied()
+    }
+
+    pub(crate) fn set_remove_edge(&mut self, component: ComponentId, target: ArchetypeId) {
+        self.edges.remove.insert(component, target);
+    }
+
+    /// Forgets the cached add edges, which go stale when a new required
+    /// component is registered.
+    pub(crate) fn clear_add_edges(&mut self) {
+        self.edges.add.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::World;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct A;
+    #[derive(Debug)]
+    struct B;
+    #[derive(Debug, Default)]
+    struct C;
+
+    fn bundle<T: 'static>() -> Bundle {
+        Bundle::Tuple(TypeId::of::<(T,)>())
+    }
+
+    fn archetype(world: &World, entity: Entity) -> ArchetypeId {
+        world.archetype_of(entity).unwrap().id()
+    }
+
+    #[test]
+    fn removes_entities() {
+        let mut archetype = Archetype::new(0, ComponentSet::new());
+        for id in 0..4 {
+            archetype.push(Entity::new(id));
+        }
+
+        // The last entity takes the place of the removed one.
+        assert!(archetype.remove(Entity::new(1)));
+        assert!(!archetype.remove(Entity::new(1)));
+        assert!(!archetype.contains(Entity::new(1)));
+        assert_eq!(archetype.entities(), [0, 3, 2].map(Entity::new));
+
+        assert_eq!(archetype.pop(), Some(Entity::new(2)));
+        assert!(archetype.remove(Entity::new(3)));
+        assert!(archetype.remove(Entity::new(0)));
+        assert!(archetype.entities().is_empty());
+    }
+
+    #[test]
+    fn caches_edges() {
+        let mut world = World::new();
+        let first = world.create_entity(A);
+        let second = world.create_entity(A);
+        let source = archetype(&world, first);
+
+        world.add_components(first, B).unwrap();
+        let target = archetype(&world, first);
+        let edge = world
+            .archetype_iter()
+            .nth(source)
+            .unwrap()
+            .add_edge(bundle::<B>());
+        assert_eq!(edge.map(|edge| edge.target), Some(target));
+
+        // Following the cached edge doesn't create another archetype.
+        let archetypes = world.archetype_iter().count();
+        world.add_components(second, B).unwrap();
+        assert_eq!(archetype(&world, second), target);
+        assert_eq!(world.archetype_iter().count(), archetypes);
+    }
+
+    #[test]
+    fn add_and_remove_round_trip() {
+        let mut world = World::new();
+        let entity = world.create_entity(A);
+        let source = archetype(&world, entity);
+
+        world.add_components(entity, B).unwrap();
+        let target = archetype(&world, entity);
+        world.remove_component::<B>(entity).unwrap();
+        assert_eq!(archetype(&world, entity), source);
+
+        let b = world.component_id::<B>().unwrap();
+        let a = world.component_id::<A>().unwrap();
+        let target = world.archetype_iter().nth(target).unwrap();
+        assert_eq!(target.remove_edge(b), Some(source));
+        assert_eq!(target.remove_edge(a), None);
+        assert!(!target.contains(entity));
+    }
+
+    #[test]
+    fn required_components_clear_add_edges() {
+        let mut world = World::new();
+        let first = world.create_entity(A);
+        let second = world.create_entity(A);
+        let source = archetype(&world, first);
+        world.add_components(first, B).unwrap();
+
+        world.register_required::<B, C>();
+        let archetype = world.archetype_iter().nth(source).unwrap();
+        assert!(archetype.add_edge(bundle::<B>()).is_none());
+
+        // The edge is rebuilt with the new requirement.
+        world.add_components(second, B).unwrap();
+        let c = world.component_id::<C>().unwrap();
+        assert!(world.archetype_of(second).unwrap().components().contains(c));
+        assert!(!world.archetype_of(first).unwrap().components().contains(c));
+    }
 }
@@ -55,13 +55,20 @@ impl<T: ?Sized> AtomicRefCell<T> {
     pub fn try_borrow(&self) -> Option<AtomicRef<'_, T>> {
         loop {
             let borrow_state = self.borrow.load(Ordering::Acquire);
-            let old =
-                self.borrow
-                    .compare_and_swap(borrow_state, borrow_state + 1, Ordering::AcqRel);
-
-            if old == Self::MUTABLY_BORROWED {
+            if borrow_state == Self::MUTABLY_BORROWED {
                 return None;
-            } else if old == borrow_state {
+            }
+
+            if self
+                .borrow
+                .compare_exchange_weak(
+                    borrow_state,
+                    borrow_state + 1,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+            {
                 break;
             }
         }
@@ -74,6 +81,9 @@ impl<T: ?Sized> AtomicRefCell<T> {
         })
     }
 
+    /// # Safety
+    ///
+    /// The cell must not be mutably borrowed.
     pub unsafe fn borrow_unchecked(&self) -> AtomicRef<'_, T> {
         let old = self.borrow.fetch_add(1, Ordering::AcqRel);
         debug_assert_ne!(old, Self::MUTABLY_BORROWED);
@@ -94,8 +104,13 @@ impl<T: ?Sized> AtomicRefCell<T> {
     pub fn try_borrow_mut(&self) -> Option<AtomicRefMut<'_, T>> {
         if self
             .borrow
-            .compare_and_swap(0, Self::MUTABLY_BORROWED, Ordering::AcqRel)
-            != 0
+            .compare_exchange(
+                0,
+                Self::MUTABLY_BORROWED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
         {
             return None;
         }
@@ -108,6 +123,9 @@ impl<T: ?Sized> AtomicRefCell<T> {
         })
     }
 
+    /// # Safety
+    ///
+    /// The cell must not be borrowed at all.
     pub unsafe fn borrow_mut_unchecked(&self) -> AtomicRefMut<'_, T> {
         let old = self.borrow.swap(Self::MUTABLY_BORROWED, Ordering::AcqRel);
         debug_assert_eq!(old, 0);
@@ -0,0 +1,293 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    cell::AtomicRef,
+    component::Component,
+    entity::EntityId,
+    storage::{ComponentStorage, ComponentStorageAllocator},
+    system::{Access, ComponentData, RetrievalError, System},
+};
+
+/// The ticks at which a value was added and last changed. A tick is
+/// taken from the world's change tick, which advances every time a
+/// system runs.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ChangeTicks {
+    added: u64,
+    changed: u64,
+}
+
+impl ChangeTicks {
+    pub fn new(tick: u64) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn added(&self) -> u64 {
+        self.added
+    }
+
+    pub fn changed(&self) -> u64 {
+        self.changed
+    }
+
+    /// Returns whether the value was added after `last_run`.
+    pub fn is_added_since(&self, last_run: u64) -> bool {
+        self.added > last_run
+    }
+
+    /// Returns whether the value was added or changed after `last_run`.
+    pub fn is_changed_since(&self, last_run: u64) -> bool {
+        self.changed > last_run
+    }
+
+    pub fn set_changed(&mut self, tick: u64) {
+        self.changed = tick;
+    }
+}
+
+/// The ticks a system is fetched with: the tick of its previous run,
+/// and the tick of the run it is being fetched for.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SystemTicks {
+    last_run: u64,
+    this_run: u64,
+}
+
+impl SystemTicks {
+    pub fn new(last_run: u64, this_run: u64) -> Self {
+        Self { last_run, this_run }
+    }
+
+    /// The tick the system last ran at, or `0` if it has never run.
+    pub fn last_run(&self) -> u64 {
+        self.last_run
+    }
+
+    pub fn this_run(&self) -> u64 {
+        self.this_run
+    }
+}
+
+/// A mutable reference to a value which marks the value as changed
+/// when it is dereferenced mutably.
+pub struct Mut<'a, T> {
+    value: &'a mut T,
+    ticks: &'a mut ChangeTicks,
+    change_tick: u64,
+}
+
+impl<'a, T> Mut<'a, T> {
+    pub fn new(value: &'a mut T, ticks: &'a mut ChangeTicks, change_tick: u64) -> Self {
+        Self {
+            value,
+            ticks,
+            change_tick,
+        }
+    }
+
+    pub fn ticks(&self) -> ChangeTicks {
+        *self.ticks
+    }
+
+    /// Returns the reference without marking the value as changed.
+    pub fn bypass_change_detection(self) -> &'a mut T {
+        self.value
+    }
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.set_changed(self.change_tick);
+        self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mut")
+            .field("value", &self.value)
+            .field("ticks", &self.ticks)
+            .finish()
+    }
+}
+
+/// Fetches the components of type `T` which were added since the
+/// fetching system last ran.
+#[derive(Debug)]
+pub struct Added<'a, T: Component> {
+    storage: AtomicRef<'a, ComponentStorage<T>>,
+    last_run: u64,
+}
+
+impl<'a, T: Component> Added<'a, T> {
+    pub fn new(storage: AtomicRef<'a, ComponentStorage<T>>, last_run: u64) -> Self {
+        Self { storage, last_run }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &'_ T)> {
+        let last_run = self.last_run;
+
+        self.storage
+            .iter_ticks()
+            .filter(move |(_, _, ticks)| ticks.is_added_since(last_run))
+            .map(|(id, comp, _)| (id, comp))
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.storage
+            .ticks_of(id)
+            .is_some_and(|ticks| ticks.is_added_since(self.last_run))
+    }
+}
+
+/// Fetches the components of type `T` which were added or changed
+/// since the fetching system last ran.
+#[derive(Debug)]
+pub struct Changed<'a, T: Component> {
+    storage: AtomicRef<'a, ComponentStorage<T>>,
+    last_run: u64,
+}
+
+impl<'a, T: Component> Changed<'a, T> {
+    pub fn new(storage: AtomicRef<'a, ComponentStorage<T>>, last_run: u64) -> Self {
+        Self { storage, last_run }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &'_ T)> {
+        let last_run = self.last_run;
+
+        self.storage
+            .iter_ticks()
+            .filter(move |(_, _, ticks)| ticks.is_changed_since(last_run))
+            .map(|(id, comp, _)| (id, comp))
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.storage
+            .ticks_of(id)
+            .is_some_and(|ticks| ticks.is_changed_since(self.last_run))
+    }
+}
+
+/// Fetches the entities which lost their component of type `T` since
+/// the fetching system last ran.
+///
+/// Removals are only kept for two ticks of the dispatcher, so a system
+/// which runs less often than once per tick can miss some of them.
+#[derive(Debug)]
+pub struct RemovedComponents<'a, T: Component> {
+    storage: AtomicRef<'a, ComponentStorage<T>>,
+    last_run: u64,
+}
+
+impl<'a, T: Component> RemovedComponents<'a, T> {
+    pub fn new(storage: AtomicRef<'a, ComponentStorage<T>>, last_run: u64) -> Self {
+        Self { storage, last_run }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.storage.removed_since(self.last_run)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+fn fetch_storage<T: Component>(
+    allocator: &ComponentStorageAllocator,
+) -> Result<AtomicRef<'_, ComponentStorage<T>>, RetrievalError> {
+    if allocator.contains::<T>() {
+        unsafe {
+            allocator
+                .try_get_unchecked::<T>()
+                .ok_or(RetrievalError::ComponentStorageInUse)
+        }
+    } else {
+        Err(RetrievalError::NoSuchComponentStorage)
+    }
+}
+
+impl<'a, T: Component> ComponentData<'a> for Added<'a, T> {
+    fn fetch(
+        allocator: &'a ComponentStorageAllocator,
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        fetch_storage(allocator).map(|storage| Self::new(storage, ticks.last_run()))
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<T>();
+    }
+}
+
+impl<'a, T: Component> ComponentData<'a> for Changed<'a, T> {
+    fn fetch(
+        allocator: &'a ComponentStorageAllocator,
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        fetch_storage(allocator).map(|storage| Self::new(storage, ticks.last_run()))
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<T>();
+    }
+}
+
+impl<'a, T: Component> ComponentData<'a> for RemovedComponents<'a, T> {
+    fn fetch(
+        allocator: &'a ComponentStorageAllocator,
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        fetch_storage(allocator).map(|storage| Self::new(storage, ticks.last_run()))
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<T>();
+    }
+}
+
+/// Every component storage, for maintenance which doesn't depend on the
+/// type of the components. Storages are borrowed one at a time, so this
+/// records no access.
+#[derive(Debug)]
+pub(crate) struct Storages<'a>(&'a ComponentStorageAllocator);
+
+impl<'a> ComponentData<'a> for Storages<'a> {
+    fn fetch(
+        allocator: &'a ComponentStorageAllocator,
+        _: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        Ok(Self(allocator))
+    }
+
+    fn access(_: &mut Access) {}
+}
+
+/// Advances the removal log of every component storage at the start of
+/// every tick.
+#[derive(Debug)]
+pub(crate) struct RemovedUpdateSystem;
+
+impl<'a> System<'a> for RemovedUpdateSystem {
+    type Resources = ();
+    type Components = Storages<'a>;
+
+    fn execute(&mut self, _: (), storages: Storages<'a>) {
+        storages.0.update_removed();
+    }
+}
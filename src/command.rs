@@ -0,0 +1,57 @@
+use std::{fmt, mem};
+
+use crate::world::World;
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// A queue of operations on the [`World`] which are deferred until the
+/// world can be borrowed mutably.
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<F>(&mut self, command: F)
+    where
+        F: FnOnce(&mut World) + Send + 'static,
+    {
+        self.queue.push(Box::new(command));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Moves every command in `other` to the end of this queue.
+    pub fn append(&mut self, other: &mut Commands) {
+        self.queue.append(&mut other.queue);
+    }
+
+    pub fn take(&mut self) -> Commands {
+        Commands {
+            queue: mem::take(&mut self.queue),
+        }
+    }
+
+    /// Runs every command in the queue, in the order they were pushed.
+    pub fn apply(self, world: &mut World) {
+        self.queue.into_iter().for_each(|command| command(world));
+    }
+}
+
+impl fmt::Debug for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Commands")
+            .field("len", &self.queue.len())
+            .finish()
+    }
+}
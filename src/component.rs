@@ -1,17 +1,35 @@
 use std::{
+    alloc::Layout,
     any::{self, TypeId},
-    collections::HashSet,
+    borrow::Cow,
+    collections::HashMap,
+    fmt, io,
+    iter::FromIterator,
+    mem,
+    sync::Arc,
 };
 
-use crate::{entity::Entity, storage::ComponentStorageAllocator};
+use crate::{
+    archetype::ArchetypeId,
+    entity::Entity,
+    storage::{ComponentStorageAllocator, StorageKind},
+};
 
 pub trait Component: Send + Sync + 'static {}
 
 impl<T: Send + Sync + 'static> Component for T {}
 
 pub trait ComponentTuple: self::sealed::ComponentTupleSealed + 'static {
-    fn set() -> HashSet<TypeId>;
-    fn store(self, entity: Entity, allocator: &mut ComponentStorageAllocator);
+    fn set(registry: &mut ComponentRegistry) -> ComponentSet;
+    /// Stores every component for the entity in the column of its
+    /// archetype, recording `change_tick` as the tick they were added at.
+    fn store(
+        self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        allocator: &mut ComponentStorageAllocator,
+        change_tick: u64,
+    );
 }
 
 pub trait IntoComponentTuple<U> {
@@ -30,26 +48,584 @@ impl<CT: ComponentTuple> IntoComponentTuple<Self> for CT {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+/// A component which can be written out through its
+/// [`ComponentInfo`], for serializing components without knowing their
+/// types.
+pub trait SerializeComponent: Component {
+    fn serialize(&self, writer: &mut dyn io::Write) -> io::Result<()>;
+}
+
+/// Drops the component at the pointer in place.
+pub type DropFn = unsafe fn(*mut u8);
+/// Clones the component at the first pointer into the uninitialized
+/// memory at the second.
+pub type CloneFn = unsafe fn(*const u8, *mut u8);
+pub type SerializeFn = unsafe fn(*const u8, &mut dyn io::Write) -> io::Result<()>;
+pub type DebugFn = unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// The optional operations of a component type, which are only known if
+/// they are set on its storage with [`ComponentStorage::with_clone`] and
+/// friends.
+///
+/// [`ComponentStorage::with_clone`]: crate::storage::ComponentStorage::with_clone
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ComponentFns {
+    clone: Option<CloneFn>,
+    serialize: Option<SerializeFn>,
+    debug: Option<DebugFn>,
+}
+
+impl ComponentFns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn with_clone<T: Component + Clone>(mut self) -> Self {
+        self.clone = Some(clone_raw::<T>);
+        self
+    }
+
+    pub(crate) fn with_serialize<T: SerializeComponent>(mut self) -> Self {
+        self.serialize = Some(serialize_raw::<T>);
+        self
+    }
+
+    pub(crate) fn with_debug<T: Component + fmt::Debug>(mut self) -> Self {
+        self.debug = Some(debug_raw::<T>);
+        self
+    }
+}
+
+unsafe fn drop_raw<T>(ptr: *mut u8) {
+    ptr.cast::<T>().drop_in_place();
+}
+
+unsafe fn clone_raw<T: Clone>(src: *const u8, dst: *mut u8) {
+    dst.cast::<T>().write((*src.cast::<T>()).clone());
+}
+
+unsafe fn serialize_raw<T: SerializeComponent>(
+    ptr: *const u8,
+    writer: &mut dyn io::Write,
+) -> io::Result<()> {
+    (*ptr.cast::<T>()).serialize(writer)
+}
+
+unsafe fn debug_raw<T: fmt::Debug>(ptr: *const u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(&*ptr.cast::<T>(), f)
+}
+
+/// What is known about a component type, recorded when its storage is
+/// registered.
+#[derive(Clone, Debug)]
+pub struct ComponentInfo {
+    id: ComponentId,
+    // `None` for dynamic components, which have no Rust type.
+    type_id: Option<TypeId>,
+    name: Cow<'static, str>,
+    layout: Layout,
+    drop: Option<DropFn>,
+    storage_kind: StorageKind,
+    fns: ComponentFns,
+}
+
+impl ComponentInfo {
+    pub(crate) fn new<T: Component>(
+        id: ComponentId,
+        storage_kind: StorageKind,
+        fns: ComponentFns,
+    ) -> Self {
+        Self {
+            id,
+            type_id: Some(TypeId::of::<T>()),
+            name: Cow::Borrowed(any::type_name::<T>()),
+            layout: Layout::new::<T>(),
+            drop: mem::needs_drop::<T>().then_some(drop_raw::<T> as DropFn),
+            storage_kind,
+            fns,
+        }
+    }
+
+    pub(crate) fn dynamic(
+        id: ComponentId,
+        name: Cow<'static, str>,
+        layout: Layout,
+        drop: Option<DropFn>,
+    ) -> Self {
+        Self {
+            id,
+            type_id: None,
+            name,
+            layout,
+            drop,
+            storage_kind: StorageKind::SparseSet,
+            fns: ComponentFns::new(),
+        }
+    }
+
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    /// The `TypeId` of the component, or `None` if it is a dynamic
+    /// component.
+    pub fn type_id(&self) -> Option<TypeId> {
+        self.type_id
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.type_id.is_none()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The function which drops the component, or `None` if it doesn't
+    /// need to be dropped.
+    pub fn drop_fn(&self) -> Option<DropFn> {
+        self.drop
+    }
+
+    pub fn storage_kind(&self) -> StorageKind {
+        self.storage_kind
+    }
+
+    pub fn clone_fn(&self) -> Option<CloneFn> {
+        self.fns.clone
+    }
+
+    pub fn serialize_fn(&self) -> Option<SerializeFn> {
+        self.fns.serialize
+    }
+
+    pub fn debug_fn(&self) -> Option<DebugFn> {
+        self.fns.debug
+    }
+}
+
+/// A dense index assigned to a component type by [`ComponentRegistry`].
+pub type ComponentId = usize;
+
+/// Assigns a [`ComponentId`] to every component type, in the order they
+/// are first seen, and records the [`ComponentInfo`] of every component
+/// type whose storage was registered. Ids are never reused, so they stay
+/// valid for the lifetime of the world.
+#[derive(Default, Debug)]
+pub struct ComponentRegistry {
+    ids: HashMap<TypeId, ComponentId>,
+    // Indexed by id. Dynamic components have no type.
+    types: Vec<Option<TypeId>>,
+    // Indexed by id. Types which were seen but never had a storage
+    // registered have no info.
+    infos: Vec<Option<ComponentInfo>>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of `T`, assigning it one if it doesn't have one
+    /// yet.
+    pub fn init<T: Component>(&mut self) -> ComponentId {
+        self.init_type_id(TypeId::of::<T>())
+    }
+
+    pub(crate) fn init_type_id(&mut self, type_id: TypeId) -> ComponentId {
+        let types = &mut self.types;
+        let infos = &mut self.infos;
+
+        *self.ids.entry(type_id).or_insert_with(|| {
+            types.push(Some(type_id));
+            infos.push(None);
+            types.len() - 1
+        })
+    }
+
+    pub fn get<T: Component>(&self) -> Option<ComponentId> {
+        self.get_type_id(TypeId::of::<T>())
+    }
+
+    pub fn get_type_id(&self, type_id: TypeId) -> Option<ComponentId> {
+        self.ids.get(&type_id).copied()
+    }
+
+    /// The `TypeId` of the component type with the id, or `None` if it
+    /// is a dynamic component.
+    pub fn type_id(&self, id: ComponentId) -> Option<TypeId> {
+        self.types.get(id).copied().flatten()
+    }
+
+    /// Returns the id of the first component registered under the name.
+    pub fn get_named(&self, name: &str) -> Option<ComponentId> {
+        self.infos()
+            .find(|info| info.name() == name)
+            .map(ComponentInfo::id)
+    }
+
+    pub fn info(&self, id: ComponentId) -> Option<&ComponentInfo> {
+        self.infos.get(id)?.as_ref()
+    }
+
+    pub fn info_of<T: Component>(&self) -> Option<&ComponentInfo> {
+        self.info(self.get::<T>()?)
+    }
+
+    /// The info of every component type whose storage was registered, in
+    /// order of id.
+    pub fn infos(&self) -> impl Iterator<Item = &'_ ComponentInfo> {
+        self.infos.iter().flatten()
+    }
+
+    /// Records the info of `T`, assigning it an id if needed.
+    pub(crate) fn register<T: Component>(&mut self, storage_kind: StorageKind, fns: ComponentFns) {
+        let id = self.init::<T>();
+        self.infos[id] = Some(ComponentInfo::new::<T>(id, storage_kind, fns));
+    }
+
+    /// Assigns an id to a new dynamic component and records its info.
+    pub(crate) fn register_dynamic(
+        &mut self,
+        name: Cow<'static, str>,
+        layout: Layout,
+        drop: Option<DropFn>,
+    ) -> ComponentId {
+        let id = self.types.len();
+        self.types.push(None);
+        self.infos
+            .push(Some(ComponentInfo::dynamic(id, name, layout, drop)));
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+}
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// A set of component types, as a bitset of their [`ComponentId`]s.
+///
+/// Trailing empty words are never kept, so equal sets are equal word for
+/// word, which keeps comparing and hashing them cheap.
+#[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
 pub struct ComponentSet {
-    ids: HashSet<TypeId>,
+    words: Vec<u64>,
 }
 
 impl ComponentSet {
-    pub fn new(ids: HashSet<TypeId>) -> Self {
-        Self { ids }
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_tuple<T: ComponentTuple>(registry: &mut ComponentRegistry) -> Self {
+        T::set(registry)
+    }
+
+    /// Adds the component to the set, returning `false` if it was
+    /// already in it.
+    pub fn insert(&mut self, id: ComponentId) -> bool {
+        let (word, bit) = (id / WORD_BITS, id % WORD_BITS);
+
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+
+        let inserted = self.words[word] & (1 << bit) == 0;
+        self.words[word] |= 1 << bit;
+        inserted
+    }
+
+    /// Removes the component from the set, returning `false` if it
+    /// wasn't in it.
+    pub fn remove(&mut self, id: ComponentId) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+
+        self.words[id / WORD_BITS] &= !(1 << (id % WORD_BITS));
+        self.trim();
+        true
+    }
+
+    pub fn contains(&self, id: ComponentId) -> bool {
+        self.words
+            .get(id / WORD_BITS)
+            .is_some_and(|word| word & (1 << (id % WORD_BITS)) != 0)
+    }
+
+    /// Returns whether every component in `self` is also in `other`.
+    pub fn is_subset(&self, other: &ComponentSet) -> bool {
+        self.words.len() <= other.words.len()
+            && self
+                .words
+                .iter()
+                .zip(&other.words)
+                .all(|(word, other)| word & !other == 0)
     }
 
-    pub fn from_tuple<T: ComponentTuple>() -> Self {
-        Self { ids: T::set() }
+    /// Returns whether every component in `other` is also in `self`.
+    pub fn is_superset(&self, other: &ComponentSet) -> bool {
+        other.is_subset(self)
     }
 
-    pub fn set(&self) -> &HashSet<TypeId> {
-        &self.ids
+    /// Returns whether `self` and `other` have no components in common.
+    pub fn is_disjoint(&self, other: &ComponentSet) -> bool {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .all(|(word, other)| word & other == 0)
     }
 
-    pub fn into_inner(self) -> HashSet<TypeId> {
-        self.ids
+    /// Adds every component in `other` to the set.
+    pub fn union_with(&mut self, other: &ComponentSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// The components in the set, in ascending order of id.
+    pub fn iter(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        self.words.iter().enumerate().flat_map(|(word, &bits)| {
+            let mut bits = bits;
+
+            std::iter::from_fn(move || {
+                if bits == 0 {
+                    return None;
+                }
+
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                Some(word * WORD_BITS + bit)
+            })
+        })
+    }
+
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+}
+
+impl FromIterator<ComponentId> for ComponentSet {
+    fn from_iter<I: IntoIterator<Item = ComponentId>>(iter: I) -> Self {
+        let mut set = Self::new();
+
+        for id in iter {
+            set.insert(id);
+        }
+
+        set
+    }
+}
+
+type BoxedStoreFn =
+    dyn FnOnce(Entity, ArchetypeId, &mut ComponentStorageAllocator, u64) + Send + Sync;
+
+/// A component with its type erased, which can be kept alongside
+/// components of other types and added to an entity with
+/// [`EntityBuilder::with_boxed`](crate::world::entity_builder::EntityBuilder::with_boxed).
+pub struct BoxedComponent {
+    name: &'static str,
+    init: fn(&mut ComponentRegistry) -> ComponentId,
+    store: Box<BoxedStoreFn>,
+}
+
+impl BoxedComponent {
+    pub fn new<T: Component>(component: T) -> Self {
+        let store = move |entity, archetype, allocator: &mut ComponentStorageAllocator, tick| {
+            (component,).store(entity, archetype, allocator, tick)
+        };
+
+        Self {
+            name: any::type_name::<T>(),
+            init: ComponentRegistry::init::<T>,
+            store: Box::new(store),
+        }
+    }
+
+    /// The type name of the component.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the id of the component, assigning it one if needed.
+    pub(crate) fn init(&self, registry: &mut ComponentRegistry) -> ComponentId {
+        (self.init)(registry)
+    }
+
+    /// Stores the component for the entity.
+    pub(crate) fn store(
+        self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        allocator: &mut ComponentStorageAllocator,
+        change_tick: u64,
+    ) {
+        (self.store)(entity, archetype, allocator, change_tick)
+    }
+}
+
+impl fmt::Debug for BoxedComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxedComponent")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+type RequiredStoreFn =
+    dyn Fn(Entity, ArchetypeId, &mut ComponentStorageAllocator, u64) + Send + Sync;
+
+/// A component which is inserted alongside another component, along with
+/// the function which constructs it.
+#[derive(Clone)]
+pub(crate) struct RequiredComponent {
+    id: ComponentId,
+    name: &'static str,
+    store: Arc<RequiredStoreFn>,
+}
+
+impl RequiredComponent {
+    fn new<T, F>(id: ComponentId, f: F) -> Self
+    where
+        T: Component,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let store =
+            move |entity: Entity, archetype, allocator: &mut ComponentStorageAllocator, tick| {
+                let mut storage = allocator.get_mut_or_register::<T>();
+                storage.set_change_tick(tick);
+                storage
+                    .push_in(Some(archetype), entity.id(), f())
+                    .unwrap_or_else(|_| {
+                        panic!(
+                            "Entity {} already contained component of type {}",
+                            entity.id(),
+                            any::type_name::<T>(),
+                        )
+                    });
+            };
+
+        Self {
+            id,
+            name: any::type_name::<T>(),
+            store: Arc::new(store),
+        }
+    }
+
+    /// Constructs the component and stores it for the entity.
+    pub(crate) fn store(
+        &self,
+        entity: Entity,
+        archetype: ArchetypeId,
+        allocator: &mut ComponentStorageAllocator,
+        change_tick: u64,
+    ) {
+        (self.store)(entity, archetype, allocator, change_tick)
+    }
+}
+
+impl fmt::Debug for RequiredComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequiredComponent")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// The components each component type requires, registered with
+/// [`World::register_required`](crate::World::register_required).
+#[derive(Default, Debug)]
+pub(crate) struct RequiredComponents {
+    required: HashMap<ComponentId, Vec<RequiredComponent>>,
+}
+
+impl RequiredComponents {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that components of type `A` require a component of type
+    /// `B`, constructed with `f`. Returns `false` if `A` already required
+    /// `B`, in which case the existing constructor is kept.
+    pub(crate) fn register<A, B, F>(&mut self, registry: &mut ComponentRegistry, f: F) -> bool
+    where
+        A: Component,
+        B: Component,
+        F: Fn() -> B + Send + Sync + 'static,
+    {
+        let (a, b) = (registry.init::<A>(), registry.init::<B>());
+        let required = self.required.entry(a).or_default();
+
+        if required.iter().any(|r| r.id == b) {
+            return false;
+        }
+
+        required.push(RequiredComponent::new(b, f));
+        true
+    }
+
+    /// Adds every component required by the components in `set` to it,
+    /// including components required by those in turn. Returns the
+    /// components which were added.
+    pub(crate) fn complete(&self, set: &mut ComponentSet) -> Vec<RequiredComponent> {
+        let from = set.clone();
+        self.complete_from(set, &from)
+    }
+
+    /// Like [`complete`](Self::complete), but only adds the components
+    /// required by the components in `from`, which were just added to
+    /// `set`. The components already in `set` are left as they are, even
+    /// if they were created before their requirements were registered.
+    pub(crate) fn complete_from(
+        &self,
+        set: &mut ComponentSet,
+        from: &ComponentSet,
+    ) -> Vec<RequiredComponent> {
+        let mut added = Vec::new();
+        let mut pending: Vec<ComponentId> = from.iter().collect();
+
+        while let Some(id) = pending.pop() {
+            let required = match self.required.get(&id) {
+                Some(required) => required,
+                None => continue,
+            };
+
+            for component in required {
+                if set.insert(component.id) {
+                    pending.push(component.id);
+                    added.push(component.clone());
+                }
+            }
+        }
+
+        added
     }
 }
 
@@ -64,11 +640,11 @@ mod sealed {
     impl ComponentTupleSealed for () {}
 
     impl ComponentTuple for () {
-        fn set() -> HashSet<TypeId> {
-            HashSet::new()
+        fn set(_: &mut ComponentRegistry) -> ComponentSet {
+            ComponentSet::new()
         }
 
-        fn store(self, _: Entity, _: &mut ComponentStorageAllocator) {}
+        fn store(self, _: Entity, _: ArchetypeId, _: &mut ComponentStorageAllocator, _: u64) {}
     }
 
     macro_rules! impl_ct {
@@ -81,23 +657,26 @@ mod sealed {
             where
                 $t: Component,
             {
-                fn set() -> HashSet<TypeId> {
-                    let mut set = HashSet::with_capacity(1);
-                    set.insert(TypeId::of::<$t>());
+                fn set(registry: &mut ComponentRegistry) -> ComponentSet {
+                    let mut set = ComponentSet::new();
+                    set.insert(registry.init::<$t>());
                     set
                 }
 
                 fn store(
                     self,
                     entity: Entity,
-                    allocator: &mut ComponentStorageAllocator
+                    archetype: ArchetypeId,
+                    allocator: &mut ComponentStorageAllocator,
+                    change_tick: u64,
                 ) {
                     #[allow(non_snake_case)]
                     let ($t,) = self;
 
-                    allocator
-                        .get_mut_or_register::<$t>()
-                        .push(entity.id(), $t)
+                    let mut storage = allocator.get_mut_or_register::<$t>();
+                    storage.set_change_tick(change_tick);
+                    storage
+                        .push_in(Some(archetype), entity.id(), $t)
                         .unwrap_or_else(|_| {
                             panic!(
                                 "Entity {} already contained component of type {}",
@@ -122,10 +701,10 @@ mod sealed {
                     $t: Component,
                 )+
             {
-                fn set() -> HashSet<TypeId> {
-                    let mut set = HashSet::with_capacity($n);
+                fn set(registry: &mut ComponentRegistry) -> ComponentSet {
+                    let mut set = ComponentSet::new();
                     $(
-                        if !set.insert(TypeId::of::<$t>()) {
+                        if !set.insert(registry.init::<$t>()) {
                             panic!(
                                 "Component set already contained component of type {}",
                                 any::type_name::<$t>(),
@@ -138,15 +717,18 @@ mod sealed {
                 fn store(
                     self,
                     entity: Entity,
-                    allocator: &mut ComponentStorageAllocator
+                    archetype: ArchetypeId,
+                    allocator: &mut ComponentStorageAllocator,
+                    change_tick: u64,
                 ) {
                     #[allow(non_snake_case)]
                     let ($($t,)+) = self;
 
-                    $(
-                        allocator
-                            .get_mut_or_register::<$t>()
-                            .push(entity.id(), $t)
+                    $({
+                        let mut storage = allocator.get_mut_or_register::<$t>();
+                        storage.set_change_tick(change_tick);
+                        storage
+                            .push_in(Some(archetype), entity.id(), $t)
                             .unwrap_or_else(|_| {
                                 panic!(
                                     "Entity {} already contained component of type {}",
@@ -154,7 +736,7 @@ mod sealed {
                                     any::type_name::<$t>(),
                                 )
                             });
-                    )+
+                    })+
                 }
             }
         };
@@ -175,12 +757,11 @@ mod sealed {
 
                 $ct: ComponentTuple,
             {
-                fn set() -> HashSet<TypeId> {
-                    let mut set = <$ct as ComponentTuple>::set();
-                    set.reserve($n);
+                fn set(registry: &mut ComponentRegistry) -> ComponentSet {
+                    let mut set = <$ct as ComponentTuple>::set(registry);
 
                     $(
-                        if !set.insert(TypeId::of::<$t>()) {
+                        if !set.insert(registry.init::<$t>()) {
                             panic!(
                                 "Component set already contained component of type {}",
                                 any::type_name::<$t>(),
@@ -194,15 +775,18 @@ mod sealed {
                 fn store(
                     self,
                     entity: Entity,
+                    archetype: ArchetypeId,
                     allocator: &mut ComponentStorageAllocator,
+                    change_tick: u64,
                 ) {
                     #[allow(non_snake_case)]
                     let ($($t,)+ $ct) = self;
 
-                    $(
-                        allocator
-                            .get_mut_or_register::<$t>()
-                            .push(entity.id(), $t)
+                    $({
+                        let mut storage = allocator.get_mut_or_register::<$t>();
+                        storage.set_change_tick(change_tick);
+                        storage
+                            .push_in(Some(archetype), entity.id(), $t)
                             .unwrap_or_else(|_| {
                                 panic!(
                                     "Entity {} already contained component of type {}",
@@ -210,10 +794,10 @@ mod sealed {
                                     any::type_name::<$t>(),
                                 )
                             });
-                    )+
+                    })+
 
 
-                    <$ct as ComponentTuple>::store($ct, entity, allocator)
+                    <$ct as ComponentTuple>::store($ct, entity, archetype, allocator, change_tick)
                 }
             }
         };
@@ -237,3 +821,167 @@ mod sealed {
     // nested tuples of components.
     impl_ct!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11; CT; 12usize);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{ManuallyDrop, MaybeUninit};
+
+    use crate::test_utils::{drops, Counted};
+
+    use super::*;
+
+    type Name = Counted<String>;
+
+    /// Formats a component through the debug function of its info.
+    struct Raw<'a>(&'a ComponentInfo, *const u8);
+
+    impl fmt::Debug for Raw<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            unsafe { (self.0.debug_fn().unwrap())(self.1, f) }
+        }
+    }
+
+    #[test]
+    fn registers_components() {
+        let mut registry = ComponentRegistry::new();
+
+        // Ids are assigned in order, and only once per type.
+        assert_eq!(registry.init::<u32>(), 0);
+        assert_eq!(registry.init::<Name>(), 1);
+        assert_eq!(registry.init::<u32>(), 0);
+        assert_eq!(registry.get::<Name>(), Some(1));
+        assert_eq!(registry.get::<u64>(), None);
+        assert_eq!(registry.type_id(1), Some(TypeId::of::<Name>()));
+
+        // Only types whose storage was registered have an info.
+        assert!(registry.info_of::<Name>().is_none());
+        registry.register::<Name>(StorageKind::SparseSet, ComponentFns::new());
+        let info = registry.info_of::<Name>().unwrap();
+        assert_eq!(info.id(), 1);
+        assert_eq!(info.type_id(), Some(TypeId::of::<Name>()));
+        assert_eq!(info.name(), any::type_name::<Name>());
+        assert_eq!(info.layout(), Layout::new::<Name>());
+        assert_eq!(info.storage_kind(), StorageKind::SparseSet);
+        assert!(!info.is_dynamic());
+
+        let layout = Layout::new::<u16>();
+        let dynamic = registry.register_dynamic(Cow::Borrowed("dynamic"), layout, None);
+        assert_eq!(dynamic, 2);
+        assert_eq!(registry.type_id(dynamic), None);
+        assert_eq!(registry.get_named("dynamic"), Some(dynamic));
+        assert!(registry.info(dynamic).unwrap().is_dynamic());
+
+        let ids = registry.infos().map(ComponentInfo::id).collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(registry.len(), 3);
+    }
+
+    #[test]
+    fn records_component_fns() {
+        let mut registry = ComponentRegistry::new();
+        registry.register::<u32>(StorageKind::Ordered, ComponentFns::new());
+        registry.register::<Name>(
+            StorageKind::Ordered,
+            ComponentFns::new()
+                .with_clone::<Name>()
+                .with_debug::<Name>(),
+        );
+
+        let plain = registry.info_of::<u32>().unwrap();
+        assert!(plain.drop_fn().is_none());
+        assert!(plain.clone_fn().is_none());
+        assert!(plain.debug_fn().is_none());
+
+        let info = registry.info_of::<Name>().unwrap();
+        let mut name = ManuallyDrop::new(Counted(String::from("a")));
+        let ptr = &mut *name as *mut Name as *mut u8;
+
+        let mut clone = MaybeUninit::<Name>::uninit();
+        unsafe { (info.clone_fn().unwrap())(ptr, clone.as_mut_ptr().cast()) };
+        let clone = unsafe { clone.assume_init() };
+        assert_eq!(clone.0, "a");
+        assert_eq!(format!("{:?}", Raw(info, ptr)), format!("{:?}", clone));
+
+        drop(clone);
+        unsafe { (info.drop_fn().unwrap())(ptr) };
+        assert_eq!(drops(), 2);
+    }
+
+    fn set(ids: &[ComponentId]) -> ComponentSet {
+        ids.iter().copied().collect()
+    }
+
+    #[test]
+    fn insert_and_contains() {
+        let mut components = ComponentSet::new();
+        assert!(components.is_empty());
+
+        assert!(components.insert(3));
+        assert!(!components.insert(3));
+        assert!(components.insert(130));
+        assert!(components.contains(3));
+        assert!(components.contains(130));
+        assert!(!components.contains(4));
+        assert!(!components.contains(1000));
+
+        assert_eq!(components.len(), 2);
+        assert_eq!(components.words.len(), 3);
+        assert_eq!(components.iter().collect::<Vec<_>>(), [3, 130]);
+    }
+
+    #[test]
+    fn remove_trims_words() {
+        let mut components = set(&[1, 64, 200]);
+
+        assert!(components.remove(64));
+        assert!(!components.remove(64));
+        assert!(!components.remove(500));
+        assert_eq!(components.words.len(), 4);
+
+        // Only trailing empty words are dropped.
+        assert!(components.remove(200));
+        assert_eq!(components.words.len(), 1);
+        assert!(components.remove(1));
+        assert!(components.words.is_empty());
+        assert!(components.is_empty());
+    }
+
+    #[test]
+    fn subsets_and_disjoint_sets() {
+        let small = set(&[1, 70]);
+        let large = set(&[1, 2, 70, 150]);
+        let other = set(&[3, 71]);
+
+        assert!(small.is_subset(&large));
+        assert!(!large.is_subset(&small));
+        assert!(large.is_superset(&small));
+        assert!(ComponentSet::new().is_subset(&small));
+        assert!(small.is_subset(&small));
+
+        assert!(small.is_disjoint(&other));
+        assert!(!small.is_disjoint(&large));
+        assert!(ComponentSet::new().is_disjoint(&large));
+    }
+
+    #[test]
+    fn union_with() {
+        let mut components = set(&[1]);
+        components.union_with(&set(&[2, 140]));
+        assert_eq!(components, set(&[1, 2, 140]));
+
+        // A shorter set leaves the longer words as they are.
+        components.union_with(&set(&[3]));
+        assert_eq!(components.iter().collect::<Vec<_>>(), [1, 2, 3, 140]);
+    }
+
+    #[test]
+    fn equal_after_trimming() {
+        let mut trimmed = set(&[5, 300]);
+        trimmed.remove(300);
+        assert_eq!(trimmed, set(&[5]));
+
+        let mut emptied = set(&[64]);
+        emptied.remove(64);
+        assert_eq!(emptied, ComponentSet::new());
+    }
+}
@@ -0,0 +1,332 @@
+use std::{iter::Chain, marker::PhantomData, mem, slice};
+
+use crate::{
+    change::SystemTicks,
+    resource::Resource,
+    storage::{ReadResource, ResourceStorageAllocator, WriteResource},
+    system::{Access, ResourceData, RetrievalError, System},
+};
+
+/// A double-buffered channel of events of type `T`, stored as a resource.
+///
+/// Events sent during a tick remain readable until the end of the
+/// following tick, after which they are dropped by the dispatcher.
+/// Channels are registered with
+/// [`DispatchBuilder::with_event`](crate::dispatch::DispatchBuilder::with_event).
+#[derive(Debug)]
+pub struct Events<T: Resource> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    previous_start: usize,
+    current_start: usize,
+}
+
+impl<T: Resource> Events<T> {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+            current_start: 0,
+        }
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// The number of events currently retained by the channel.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The total number of events ever sent through the channel.
+    pub fn event_count(&self) -> usize {
+        self.current_start + self.current.len()
+    }
+
+    /// Drops the events sent before the previous tick, and moves the
+    /// events sent during this tick into the previous tick's buffer.
+    /// This is called by the dispatcher at the start of every tick.
+    pub fn update(&mut self) {
+        self.previous.clear();
+        mem::swap(&mut self.previous, &mut self.current);
+
+        self.previous_start = self.current_start;
+        self.current_start += self.previous.len();
+    }
+
+    /// Drops every event in the channel without affecting the event count.
+    pub fn clear(&mut self) {
+        self.update();
+        self.update();
+    }
+
+    /// Returns every retained event sent at or after `cursor`, and the
+    /// cursor pointing after the last of those events.
+    fn read_from(&self, cursor: usize) -> (EventIter<'_, T>, usize) {
+        let previous_skip = cursor.saturating_sub(self.previous_start);
+        let current_skip = cursor.saturating_sub(self.current_start);
+
+        let previous = self.previous.get(previous_skip..).unwrap_or_default();
+        let current = self.current.get(current_skip..).unwrap_or_default();
+
+        (
+            EventIter {
+                inner: previous.iter().chain(current.iter()),
+            },
+            self.event_count(),
+        )
+    }
+}
+
+impl<T: Resource> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An iterator over events read by an [`EventReader`].
+#[derive(Debug)]
+pub struct EventIter<'a, T> {
+    inner: Chain<slice::Iter<'a, T>, slice::Iter<'a, T>>,
+}
+
+impl<'a, T> Iterator for EventIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for EventIter<'a, T> {}
+
+/// A system resource which reads the events in [`Events<T>`] that the
+/// system has not read yet. Each system keeps its own position in the
+/// channel, so every reader sees every event once.
+#[derive(Debug)]
+pub struct EventReader<'a, T: Resource> {
+    events: ReadResource<'a, Events<T>>,
+    cursor: &'a mut usize,
+}
+
+impl<'a, T: Resource> EventReader<'a, T> {
+    /// Returns the unread events and marks them as read.
+    pub fn iter(&mut self) -> EventIter<'_, T> {
+        let (iter, cursor) = self.events.read_from(*self.cursor);
+        *self.cursor = cursor;
+        iter
+    }
+
+    /// The number of unread events.
+    pub fn len(&self) -> usize {
+        self.events.read_from(*self.cursor).0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks every event as read without reading them.
+    pub fn clear(&mut self) {
+        *self.cursor = self.events.event_count();
+    }
+}
+
+/// A system resource which sends events through [`Events<T>`].
+#[derive(Debug)]
+pub struct EventWriter<'a, T: Resource> {
+    events: WriteResource<'a, Events<T>>,
+}
+
+impl<'a, T: Resource> EventWriter<'a, T> {
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+
+    pub fn send_batch<I: IntoIterator<Item = T>>(&mut self, events: I) {
+        self.events.current.extend(events);
+    }
+}
+
+impl<'a, T: Resource> ResourceData<'a> for EventReader<'a, T> {
+    type State = usize;
+
+    fn init_state() -> usize {
+        0
+    }
+
+    fn fetch(
+        allocator: &'a ResourceStorageAllocator,
+        cursor: &'a mut usize,
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        if allocator.contains::<Events<T>>() {
+            let storage = unsafe {
+                allocator
+                    .try_get_unchecked::<Events<T>>()
+                    .ok_or(RetrievalError::ResourceStorageInUse)?
+            };
+            let events = ReadResource::new(storage, ticks.last_run());
+            Ok(Self { events, cursor })
+        } else {
+            Err(RetrievalError::NoSuchResourceStorage)
+        }
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<Events<T>>();
+    }
+}
+
+impl<'a, T: Resource> ResourceData<'a> for EventWriter<'a, T> {
+    type State = ();
+
+    fn init_state() {}
+
+    fn fetch(
+        allocator: &'a ResourceStorageAllocator,
+        _: &'a mut (),
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        if allocator.contains::<Events<T>>() {
+            let storage = unsafe {
+                allocator
+                    .try_get_mut_unchecked::<Events<T>>()
+                    .ok_or(RetrievalError::ResourceStorageInUse)?
+            };
+            let events = WriteResource::new(storage, ticks);
+            Ok(Self { events })
+        } else {
+            Err(RetrievalError::NoSuchResourceStorage)
+        }
+    }
+
+    fn access(access: &mut Access) {
+        access.write::<Events<T>>();
+    }
+}
+
+/// Swaps the buffers of [`Events<T>`] at the start of every tick.
+#[derive(Debug)]
+pub(crate) struct EventUpdateSystem<T: Resource> {
+    _spooky: PhantomData<fn() -> T>,
+}
+
+impl<T: Resource> EventUpdateSystem<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            _spooky: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Resource> System<'a> for EventUpdateSystem<T> {
+    type Resources = (WriteResource<'a, Events<T>>,);
+    type Components = ();
+
+    fn execute(&mut self, (mut events,): Self::Resources, _: ()) {
+        events.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{dispatch::DispatchBuilder, world::World};
+
+    use super::*;
+
+    fn read(events: &Events<u32>, cursor: &mut usize) -> Vec<u32> {
+        let (iter, next) = events.read_from(*cursor);
+        let read = iter.copied().collect();
+        *cursor = next;
+        read
+    }
+
+    #[test]
+    fn reads_each_event_once() {
+        let mut events = Events::new();
+        let mut cursor = 0;
+
+        events.send(1);
+        assert_eq!(read(&events, &mut cursor), [1]);
+        assert!(read(&events, &mut cursor).is_empty());
+
+        // The event moves to the previous buffer, but isn't read again.
+        events.update();
+        assert!(read(&events, &mut cursor).is_empty());
+
+        events.send(2);
+        assert_eq!(read(&events, &mut cursor), [2]);
+    }
+
+    #[test]
+    fn events_survive_one_update() {
+        let mut events = Events::new();
+        events.send(1);
+
+        events.update();
+        assert_eq!(events.len(), 1);
+        assert_eq!(read(&events, &mut 0), [1]);
+
+        events.update();
+        assert!(events.is_empty());
+        assert!(read(&events, &mut 0).is_empty());
+        assert_eq!(events.event_count(), 1);
+    }
+
+    struct Reader(Arc<Mutex<Vec<u32>>>);
+
+    impl<'a> System<'a> for Reader {
+        type Resources = (EventReader<'a, u32>,);
+        type Components = ();
+
+        fn execute(&mut self, (mut reader,): Self::Resources, _: ()) {
+            self.0.lock().extend(reader.iter().copied());
+        }
+    }
+
+    #[test]
+    fn readers_see_events_once() {
+        let read = Arc::new(Mutex::new(Vec::new()));
+        let mut dispatcher = DispatchBuilder::new()
+            .with_event::<u32>()
+            .with_event::<u32>()
+            .with_system(Reader(Arc::clone(&read)))
+            .with_threads(0)
+            .build(World::new())
+            .unwrap();
+
+        // The buffers are swapped once when the tick starts, so the event
+        // is still readable.
+        dispatcher
+            .world()
+            .get_resource_mut::<Events<u32>>()
+            .unwrap()
+            .send(1);
+        dispatcher.run_once();
+        assert_eq!(*read.lock(), [1]);
+
+        dispatcher.run_once();
+        dispatcher.run_once();
+        assert_eq!(*read.lock(), [1]);
+        assert!(dispatcher
+            .world()
+            .get_resource::<Events<u32>>()
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,18 @@
+use crate::entity::Entity;
+
+/// The parent of an entity. Events triggered with
+/// [`World::trigger_propagating`](crate::World::trigger_propagating)
+/// travel from an entity to its parent, then to the parent's parent and
+/// so on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn new(parent: Entity) -> Self {
+        Self(parent)
+    }
+
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
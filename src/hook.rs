@@ -0,0 +1,168 @@
+use crate::{command::Commands, entity::Entity};
+
+/// A function run when a component of a specific type is added to,
+/// replaced on or removed from an entity. Hooks can't access the world
+/// directly, but can push commands which are applied once the world can
+/// be borrowed mutably.
+pub type ComponentHook = fn(Entity, &mut Commands);
+
+/// The hooks of a single component type, registered along with its
+/// storage through
+/// [`ComponentStorageAllocator::register_with`](crate::storage::ComponentStorageAllocator::register_with).
+///
+/// When a component is stored, `on_add` runs if the entity didn't have
+/// one yet and `on_replace` runs if it did, then `on_insert` runs. When a
+/// component is removed, `on_replace` runs, then `on_remove`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ComponentHooks {
+    on_add: Option<ComponentHook>,
+    on_insert: Option<ComponentHook>,
+    on_replace: Option<ComponentHook>,
+    on_remove: Option<ComponentHook>,
+}
+
+impl ComponentHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_add(mut self, hook: ComponentHook) -> Self {
+        self.on_add = Some(hook);
+        self
+    }
+
+    pub fn on_insert(mut self, hook: ComponentHook) -> Self {
+        self.on_insert = Some(hook);
+        self
+    }
+
+    pub fn on_replace(mut self, hook: ComponentHook) -> Self {
+        self.on_replace = Some(hook);
+        self
+    }
+
+    pub fn on_remove(mut self, hook: ComponentHook) -> Self {
+        self.on_remove = Some(hook);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.on_add.is_none()
+            && self.on_insert.is_none()
+            && self.on_replace.is_none()
+            && self.on_remove.is_none()
+    }
+
+    pub(crate) fn run_add(&self, entity: Entity, commands: &mut Commands) {
+        if let Some(hook) = self.on_add {
+            hook(entity, commands);
+        }
+    }
+
+    pub(crate) fn run_insert(&self, entity: Entity, commands: &mut Commands) {
+        if let Some(hook) = self.on_insert {
+            hook(entity, commands);
+        }
+    }
+
+    pub(crate) fn run_replace(&self, entity: Entity, commands: &mut Commands) {
+        if let Some(hook) = self.on_replace {
+            hook(entity, commands);
+        }
+    }
+
+    pub(crate) fn run_remove(&self, entity: Entity, commands: &mut Commands) {
+        if let Some(hook) = self.on_remove {
+            hook(entity, commands);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::{const_mutex, Mutex};
+
+    use crate::{component::Component, storage::ComponentStorage, world::World};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Health(u32);
+
+    #[derive(Debug)]
+    struct Marker;
+
+    static ORDER: Mutex<Vec<&str>> = const_mutex(Vec::new());
+
+    fn has<T: Component>(world: &World, entity: Entity) -> bool {
+        let id = world.component_id::<T>().unwrap();
+        world
+            .archetype_of(entity)
+            .unwrap()
+            .components()
+            .contains(id)
+    }
+
+    #[test]
+    fn hooks_run_in_order() {
+        let mut world = World::new();
+        world.register_component_with::<Health, _>(|| {
+            ComponentStorage::new().with_hooks(
+                ComponentHooks::new()
+                    .on_add(|_, _| ORDER.lock().push("add"))
+                    .on_insert(|_, _| ORDER.lock().push("insert"))
+                    .on_replace(|_, _| ORDER.lock().push("replace"))
+                    .on_remove(|_, _| ORDER.lock().push("remove")),
+            )
+        });
+
+        let entity = world.create_entity(Health(1));
+        assert_eq!(*ORDER.lock(), ["add", "insert"]);
+        ORDER.lock().clear();
+
+        world
+            .component_storage()
+            .read()
+            .get_mut::<Health>()
+            .unwrap()
+            .insert(entity.id(), Health(2));
+        assert_eq!(*ORDER.lock(), ["replace", "insert"]);
+        ORDER.lock().clear();
+
+        assert_eq!(world.remove_component::<Health>(entity).unwrap().0, 2);
+        assert_eq!(*ORDER.lock(), ["replace", "remove"]);
+    }
+
+    static SEEN: Mutex<Vec<bool>> = const_mutex(Vec::new());
+
+    #[test]
+    fn commands_run_after_the_change() {
+        let mut world = World::new();
+        world.register_component_with::<Health, _>(|| {
+            ComponentStorage::new().with_hooks(
+                ComponentHooks::new()
+                    .on_add(|entity, commands| {
+                        commands.push(move |world| {
+                            SEEN.lock().push(has::<Health>(world, entity));
+                            world.add_components(entity, Marker).unwrap();
+                        });
+                    })
+                    .on_remove(|entity, commands| {
+                        commands.push(move |world| {
+                            SEEN.lock().push(has::<Health>(world, entity));
+                            world.remove_component::<Marker>(entity);
+                        });
+                    }),
+            )
+        });
+
+        // The commands see the entity once the component was added or
+        // removed, and are applied before the world returns.
+        let entity = world.create_entity(Health(1));
+        assert!(has::<Marker>(&world, entity));
+
+        world.remove_component::<Health>(entity);
+        assert!(!has::<Marker>(&world, entity));
+        assert_eq!(*SEEN.lock(), [true, false]);
+    }
+}
@@ -0,0 +1,520 @@
+use std::marker::PhantomData;
+
+use crate::{
+    archetype::ArchetypeId,
+    change::{ChangeTicks, Mut},
+    component::Component,
+    entity::EntityId,
+    storage::{
+        contains_tag, value_mut, value_ref, ColumnRef, ComponentStorage, Locator, RawColumn,
+        RawSparseArray, ReadComponent, WriteComponent,
+    },
+};
+
+/// The entities of a column of a storage, by row.
+#[derive(Copy, Clone, Debug)]
+pub enum Rows<'a> {
+    /// The entity of every row.
+    Ids(&'a [EntityId]),
+    /// A bitset of entity indices, where the row of an entity is its
+    /// index and rows without a set bit are empty.
+    Bits(&'a [u64]),
+}
+
+impl Rows<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Ids(ids) => ids.len(),
+            Self::Bits(words) => words.len() * u64::BITS as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the entity at the row, or `None` if the row is empty.
+    pub fn get(&self, row: usize) -> Option<EntityId> {
+        match self {
+            Self::Ids(ids) => ids.get(row).copied(),
+            Self::Bits(words) => {
+                let id = row as EntityId;
+                contains_tag(words, id).then_some(id)
+            }
+        }
+    }
+}
+
+/// A borrow of one or more component storages which can be iterated
+/// together, yielding the components of every entity which has all of
+/// them.
+///
+/// The iteration is driven by the first storage. When the storages are
+/// tables, the components of an archetype are read row by row from the
+/// columns of that archetype, rather than looked up by entity.
+pub trait Join<'a> {
+    type Fetch: Fetch<'a>;
+
+    fn into_fetch(self) -> Self::Fetch;
+
+    fn join(self) -> JoinIter<'a, Self::Fetch>
+    where
+        Self: Sized,
+    {
+        JoinIter::new(self.into_fetch())
+    }
+}
+
+/// The borrowed columns of the storages of a [`Join`].
+///
+/// # Safety
+///
+/// `fetch` must return items which don't alias each other, as long as it
+/// is called with distinct entities.
+pub unsafe trait Fetch<'a> {
+    type Item;
+
+    /// The archetype and entities of every column, which the join is
+    /// driven by when this is its first storage.
+    fn columns(&self) -> Vec<(Option<ArchetypeId>, Rows<'a>)>;
+
+    /// Prepares to fetch the rows of the column of `archetype`.
+    fn seek(&mut self, archetype: Option<ArchetypeId>);
+
+    /// Fetches the component of the entity, which is expected at `row` of
+    /// the column found by [`seek`](Self::seek).
+    ///
+    /// # Safety
+    ///
+    /// This must not be called for an entity while an item previously
+    /// fetched for it is still alive.
+    unsafe fn fetch(&mut self, id: EntityId, row: usize) -> Option<Self::Item>;
+}
+
+/// Iterates the entities of a [`Join`] along with their components.
+#[derive(Debug)]
+pub struct JoinIter<'a, F: Fetch<'a>> {
+    fetch: F,
+    columns: Vec<(Option<ArchetypeId>, Rows<'a>)>,
+    column: usize,
+    row: usize,
+}
+
+impl<'a, F: Fetch<'a>> JoinIter<'a, F> {
+    pub fn new(fetch: F) -> Self {
+        let columns = fetch.columns();
+
+        Self {
+            fetch,
+            columns,
+            column: 0,
+            row: 0,
+        }
+    }
+}
+
+impl<'a, F: Fetch<'a>> Iterator for JoinIter<'a, F> {
+    type Item = (EntityId, F::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (archetype, rows) = *self.columns.get(self.column)?;
+
+            if self.row >= rows.len() {
+                self.column += 1;
+                self.row = 0;
+                continue;
+            }
+
+            if self.row == 0 {
+                self.fetch.seek(archetype);
+            }
+
+            let row = self.row;
+            self.row += 1;
+
+            let id = match rows.get(row) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            // SAFETY: every row of the first storage is visited once, and
+            // a storage holds at most one component per entity, so no
+            // entity is fetched twice.
+            if let Some(item) = unsafe { self.fetch.fetch(id, row) } {
+                return Some((id, item));
+            }
+        }
+    }
+}
+
+/// Finds the row of the entity in the column found by `seek`, falling
+/// back to looking the entity up when the rows don't line up.
+fn find(
+    locator: &Locator<'_>,
+    ids: Option<&[EntityId]>,
+    current: Option<usize>,
+    id: EntityId,
+    row: usize,
+) -> Option<(usize, usize)> {
+    match (current, ids) {
+        (Some(column), Some(ids)) if ids.get(row) == Some(&id) => Some((column, row)),
+        _ => locator.locate(id),
+    }
+}
+
+#[derive(Debug)]
+pub struct ReadFetch<'a, T> {
+    columns: Vec<ColumnRef<'a, T>>,
+    locator: Locator<'a>,
+    tag_words: &'a [u64],
+    current: Option<usize>,
+}
+
+unsafe impl<'a, T: Component> Fetch<'a> for ReadFetch<'a, T> {
+    type Item = &'a T;
+
+    fn columns(&self) -> Vec<(Option<ArchetypeId>, Rows<'a>)> {
+        let mut columns: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| (column.archetype, Rows::Ids(column.ids)))
+            .collect();
+
+        if !self.tag_words.is_empty() {
+            columns.push((None, Rows::Bits(self.tag_words)));
+        }
+
+        columns
+    }
+
+    fn seek(&mut self, archetype: Option<ArchetypeId>) {
+        self.current = self.locator.column_of(archetype);
+    }
+
+    unsafe fn fetch(&mut self, id: EntityId, row: usize) -> Option<&'a T> {
+        if contains_tag(self.tag_words, id) {
+            return Some(value_ref());
+        }
+
+        let ids = self.current.map(|column| self.columns[column].ids);
+        let (column, row) = find(&self.locator, ids, self.current, id, row)?;
+
+        Some(&self.columns[column].comps[row])
+    }
+}
+
+#[derive(Debug)]
+pub struct WriteFetch<'a, T> {
+    columns: Vec<RawColumn<'a, T>>,
+    locator: Locator<'a>,
+    tag_words: &'a [u64],
+    tag_ticks: RawSparseArray<'a, ChangeTicks>,
+    current: Option<usize>,
+    change_tick: u64,
+    _marker: PhantomData<&'a mut T>,
+}
+
+unsafe impl<'a, T: Component> Fetch<'a> for WriteFetch<'a, T> {
+    type Item = Mut<'a, T>;
+
+    fn columns(&self) -> Vec<(Option<ArchetypeId>, Rows<'a>)> {
+        let mut columns: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| (column.archetype, Rows::Ids(column.ids)))
+            .collect();
+
+        if !self.tag_words.is_empty() {
+            columns.push((None, Rows::Bits(self.tag_words)));
+        }
+
+        columns
+    }
+
+    fn seek(&mut self, archetype: Option<ArchetypeId>) {
+        self.current = self.locator.column_of(archetype);
+    }
+
+    unsafe fn fetch(&mut self, id: EntityId, row: usize) -> Option<Mut<'a, T>> {
+        if contains_tag(self.tag_words, id) {
+            // SAFETY: the caller guarantees the ticks of the entity aren't
+            // borrowed.
+            let ticks: &'a mut ChangeTicks = &mut *self.tag_ticks.get(id)?;
+            return Some(Mut::new(value_mut(), ticks, self.change_tick));
+        }
+
+        let ids = self.current.map(|column| self.columns[column].ids);
+        let (column, row) = find(&self.locator, ids, self.current, id, row)?;
+        let column = &self.columns[column];
+
+        // SAFETY: the row is in bounds of the column, and the caller
+        // guarantees no other item borrows the component of this entity.
+        let comp: &'a mut T = &mut *column.comps.add(row);
+        let ticks: &'a mut ChangeTicks = &mut *column.ticks.add(row);
+
+        Some(Mut::new(comp, ticks, self.change_tick))
+    }
+}
+
+impl<'a, T: Component> Join<'a> for &'a ComponentStorage<T> {
+    type Fetch = ReadFetch<'a, T>;
+
+    fn into_fetch(self) -> ReadFetch<'a, T> {
+        ReadFetch {
+            columns: self.columns().collect(),
+            locator: self.locator(),
+            tag_words: self.tag_words(),
+            current: None,
+        }
+    }
+}
+
+impl<'a, T: Component> Join<'a> for &'a mut ComponentStorage<T> {
+    type Fetch = WriteFetch<'a, T>;
+
+    fn into_fetch(self) -> WriteFetch<'a, T> {
+        let parts = self.raw_parts();
+
+        WriteFetch {
+            columns: parts.columns,
+            locator: parts.locator,
+            tag_words: parts.tag_words,
+            tag_ticks: parts.tag_ticks,
+            current: None,
+            change_tick: parts.change_tick,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Component> Join<'a> for &'a ReadComponent<'_, T> {
+    type Fetch = ReadFetch<'a, T>;
+
+    fn into_fetch(self) -> ReadFetch<'a, T> {
+        (&**self).into_fetch()
+    }
+}
+
+impl<'a, T: Component> Join<'a> for &'a WriteComponent<'_, T> {
+    type Fetch = ReadFetch<'a, T>;
+
+    fn into_fetch(self) -> ReadFetch<'a, T> {
+        (&**self).into_fetch()
+    }
+}
+
+impl<'a, T: Component> Join<'a> for &'a mut WriteComponent<'_, T> {
+    type Fetch = WriteFetch<'a, T>;
+
+    fn into_fetch(self) -> WriteFetch<'a, T> {
+        (&mut **self).into_fetch()
+    }
+}
+
+macro_rules! impl_join {
+    ($t0:tt $(, $t:tt)*) => {
+        impl<'a, $t0 $(, $t)*> Join<'a> for ($t0, $($t,)*)
+        where
+            $t0: Join<'a>,
+            $(
+                $t: Join<'a>,
+            )*
+        {
+            type Fetch = ($t0::Fetch, $($t::Fetch,)*);
+
+            fn into_fetch(self) -> Self::Fetch {
+                #[allow(non_snake_case)]
+                let ($t0, $($t,)*) = self;
+
+                ($t0.into_fetch(), $($t.into_fetch(),)*)
+            }
+        }
+
+        unsafe impl<'a, $t0 $(, $t)*> Fetch<'a> for ($t0, $($t,)*)
+        where
+            $t0: Fetch<'a>,
+            $(
+                $t: Fetch<'a>,
+            )*
+        {
+            type Item = ($t0::Item, $($t::Item,)*);
+
+            fn columns(&self) -> Vec<(Option<ArchetypeId>, Rows<'a>)> {
+                self.0.columns()
+            }
+
+            fn seek(&mut self, archetype: Option<ArchetypeId>) {
+                #[allow(non_snake_case)]
+                let ($t0, $($t,)*) = self;
+
+                $t0.seek(archetype);
+                $(
+                    $t.seek(archetype);
+                )*
+            }
+
+            unsafe fn fetch(&mut self, id: EntityId, row: usize) -> Option<Self::Item> {
+                #[allow(non_snake_case)]
+                let ($t0, $($t,)*) = self;
+
+                Some(($t0.fetch(id, row)?, $($t.fetch(id, row)?,)*))
+            }
+        }
+    };
+}
+
+impl_join!(T0);
+impl_join!(T0, T1);
+impl_join!(T0, T1, T2);
+impl_join!(T0, T1, T2, T3);
+impl_join!(T0, T1, T2, T3, T4);
+impl_join!(T0, T1, T2, T3, T4, T5);
+impl_join!(T0, T1, T2, T3, T4, T5, T6);
+impl_join!(T0, T1, T2, T3, T4, T5, T6, T7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageKind;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Marker;
+
+    fn storage<T: Component>(kind: StorageKind, comps: Vec<(EntityId, T)>) -> ComponentStorage<T> {
+        let mut storage = ComponentStorage::with_kind(kind);
+        for (id, comp) in comps {
+            assert!(storage.push(id, comp).is_ok());
+        }
+        storage
+    }
+
+    #[test]
+    fn join_skips_missing_components() {
+        let positions = storage(
+            StorageKind::SparseSet,
+            (0..4).map(|id| (id, Position(id as i32))).collect(),
+        );
+        let velocities = storage(
+            StorageKind::HashMap,
+            vec![(1, Velocity(10)), (3, Velocity(30))],
+        );
+
+        let joined: Vec<_> = (&positions, &velocities)
+            .join()
+            .map(|(id, (pos, vel))| (id, pos.0, vel.0))
+            .collect();
+
+        assert_eq!(joined, vec![(1, 1, 10), (3, 3, 30)]);
+    }
+
+    #[test]
+    fn write_fetch_marks_changed() {
+        let mut positions = storage(
+            StorageKind::SparseSet,
+            (0..3).map(|id| (id, Position(0))).collect(),
+        );
+        let velocities = storage(
+            StorageKind::SparseSet,
+            (0..3).map(|id| (id, Velocity(id as i32 + 1))).collect(),
+        );
+        positions.set_change_tick(5);
+
+        for (id, (mut pos, vel)) in (&mut positions, &velocities).join() {
+            if id != 1 {
+                pos.0 += vel.0;
+            }
+        }
+
+        assert_eq!(positions.get(0), Some(&Position(1)));
+        assert_eq!(positions.get(1), Some(&Position(0)));
+        assert_eq!(positions.get(2), Some(&Position(3)));
+        assert_eq!(positions.ticks_of(0).map(|ticks| ticks.changed()), Some(5));
+        assert_eq!(positions.ticks_of(1).map(|ticks| ticks.changed()), Some(0));
+    }
+
+    #[test]
+    fn write_fetch_items_dont_alias() {
+        // Every item is fetched before any of them is written, so the
+        // pointers handed out for different rows must stay valid while
+        // the others are alive.
+        let mut positions = storage(
+            StorageKind::Table,
+            (0..8).map(|id| (id, Position(id as i32))).collect(),
+        );
+        let mut velocities = storage(
+            StorageKind::SparseSet,
+            (0..8).map(|id| (id, Velocity(0))).collect(),
+        );
+
+        let mut items: Vec<_> = (&mut positions, &mut velocities).join().collect();
+        for (id, (pos, vel)) in items.iter_mut() {
+            pos.0 *= 2;
+            vel.0 = *id as i32;
+        }
+        drop(items);
+
+        for id in 0..8 {
+            assert_eq!(positions.get(id), Some(&Position(id as i32 * 2)));
+            assert_eq!(velocities.get(id), Some(&Velocity(id as i32)));
+        }
+    }
+
+    #[test]
+    fn table_columns_join_in_lockstep() {
+        let mut positions = ComponentStorage::table();
+        let mut velocities = ComponentStorage::table();
+
+        for id in 0..6 {
+            let archetype = (id % 2) as ArchetypeId;
+            assert!(positions
+                .push_in(Some(archetype), id, Position(id as i32))
+                .is_ok());
+            assert!(velocities
+                .push_in(Some(archetype), id, Velocity(-(id as i32)))
+                .is_ok());
+        }
+
+        // Moving a component out of its column must not break the join
+        // of the columns it left and entered.
+        positions.move_to(2, 1);
+        velocities.move_to(2, 1);
+
+        let mut joined: Vec<_> = (&mut positions, &velocities)
+            .join()
+            .map(|(id, (mut pos, vel))| {
+                pos.0 += vel.0;
+                (id, pos.0)
+            })
+            .collect();
+        joined.sort_unstable();
+
+        assert_eq!(joined, (0..6).map(|id| (id, 0)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn write_fetch_tags() {
+        let mut markers = storage(StorageKind::Null, vec![(1, Marker), (64, Marker)]);
+        let positions = storage(
+            StorageKind::SparseSet,
+            (0..70).map(|id| (id, Position(id as i32))).collect(),
+        );
+        markers.set_change_tick(3);
+
+        let items: Vec<_> = (&mut markers, &positions).join().collect();
+        let ids: Vec<_> = items.iter().map(|(id, _)| *id).collect();
+        for (_, (mut marker, _)) in items {
+            *marker = Marker;
+        }
+
+        assert_eq!(ids, vec![1, 64]);
+        assert_eq!(markers.ticks_of(64).map(|ticks| ticks.changed()), Some(3));
+        assert_eq!(markers.len(), 2);
+    }
+}
@@ -1,19 +1,40 @@
 #![deny(missing_debug_implementations)]
 
 pub use archetype::Archetype;
-pub use component::{Component, ComponentSet, ComponentTuple, IntoComponentTuple};
+pub use change::{Added, Changed, Mut, RemovedComponents};
+pub use command::Commands;
+pub use component::{
+    BoxedComponent, Component, ComponentId, ComponentInfo, ComponentRegistry, ComponentSet,
+    ComponentTuple, IntoComponentTuple, SerializeComponent,
+};
 pub use entity::Entity;
+pub use event::{EventReader, EventWriter, Events};
+pub use hierarchy::Parent;
+pub use hook::ComponentHooks;
+pub use join::Join;
+pub use observer::{Observer, Trigger};
 pub use resource::{IntoResourceTuple, Resource, ResourceTuple};
-pub use storage::{ReadComponent, ReadResource, WriteComponent, WriteResource};
-pub use system::{dispatch, System};
-pub use world::{query, World};
+pub use storage::{
+    NonSend, NonSendMut, ReadComponent, ReadResource, WriteComponent, WriteResource,
+};
+pub use system::{dispatch, init::Init, local::Local, System};
+pub use world::{dynamic_query, query, FromWorld, World};
 
 pub mod archetype;
 pub mod cell;
+pub mod change;
+pub mod command;
 pub mod component;
 pub mod entity;
+pub mod event;
+pub mod hierarchy;
+pub mod hook;
+pub mod join;
+pub mod observer;
 pub mod resource;
 pub mod storage;
 pub mod system;
+#[cfg(test)]
+mod test_utils;
 pub mod utils;
 pub mod world;
@@ -0,0 +1,451 @@
+use std::{
+    any::{self, Any, TypeId},
+    collections::HashMap,
+    fmt,
+    mem::ManuallyDrop,
+};
+
+use crate::{
+    entity::{Entity, EntityId},
+    system::{self, AccessError, ComponentData, ResourceData, RetrievalError, SystemAccess},
+    utils,
+    world::World,
+};
+
+/// An event triggered on an entity with [`World::trigger`], as seen by
+/// the observers of the entity it is currently at.
+#[derive(Debug)]
+pub struct Trigger<E> {
+    event: E,
+    target: Entity,
+    origin: Entity,
+    propagate: bool,
+}
+
+impl<E> Trigger<E> {
+    fn new(event: E, target: Entity, propagate: bool) -> Self {
+        Self {
+            event,
+            target,
+            origin: target,
+            propagate,
+        }
+    }
+
+    pub fn event(&self) -> &E {
+        &self.event
+    }
+
+    pub fn event_mut(&mut self) -> &mut E {
+        &mut self.event
+    }
+
+    pub fn into_event(self) -> E {
+        self.event
+    }
+
+    /// The entity the event is currently at.
+    pub fn target(&self) -> Entity {
+        self.target
+    }
+
+    /// The entity the event was triggered on.
+    pub fn origin(&self) -> Entity {
+        self.origin
+    }
+
+    /// Sets whether the event continues to the [`Parent`] of the
+    /// current target once its observers have run.
+    ///
+    /// [`Parent`]: crate::hierarchy::Parent
+    pub fn propagate(&mut self, propagate: bool) {
+        self.propagate = propagate;
+    }
+
+    pub fn is_propagating(&self) -> bool {
+        self.propagate
+    }
+}
+
+/// A system which runs immediately whenever an event of type `E` is
+/// triggered, rather than being queued by the dispatcher.
+pub trait Observer<'a, E> {
+    type Resources: ResourceData<'a>;
+    type Components: ComponentData<'a>;
+
+    fn observe(&mut self, _: &mut Trigger<E>, _: Self::Resources, _: Self::Components);
+}
+
+/// An observer with its resource state, erased over the observer type.
+trait ErasedObserver<E>: Send + Sync {
+    fn run(&mut self, trigger: &mut Trigger<E>, world: &World) -> Result<(), RetrievalError>;
+}
+
+struct ObserverState<O> {
+    observer: O,
+    // See `executor::Inner` for why this is type-erased.
+    state: Box<dyn Any + Send + Sync>,
+    last_run: u64,
+}
+
+impl<O> ObserverState<O> {
+    fn new<E>(observer: O) -> Self
+    where
+        O: for<'a> Observer<'a, E>,
+    {
+        let state = Box::new(<O::Resources as ResourceData<'_>>::init_state());
+
+        Self {
+            observer,
+            state,
+            last_run: 0,
+        }
+    }
+}
+
+impl<E, O> ErasedObserver<E> for ObserverState<O>
+where
+    O: for<'a> Observer<'a, E> + Send + Sync,
+{
+    fn run(&mut self, trigger: &mut Trigger<E>, world: &World) -> Result<(), RetrievalError> {
+        let resource_guard = world
+            .resource_storage()
+            .try_read()
+            .ok_or(RetrievalError::ResourceLockedExclusive)?;
+        let component_guard = world
+            .component_storage()
+            .try_read()
+            .ok_or(RetrievalError::ComponentLockedExclusive)?;
+
+        let state = self
+            .state
+            .downcast_mut::<<O::Resources as ResourceData<'_>>::State>()
+            .unwrap_or_else(|| unsafe {
+                utils::debug_unreachable("Observer state did not match its resource state type.")
+            });
+
+        let (resources, components, ticks) = system::fetch::<O::Resources, O::Components>(
+            world,
+            &resource_guard,
+            &component_guard,
+            state,
+            self.last_run,
+        )?;
+
+        self.observer.observe(trigger, resources, components);
+        self.last_run = ticks.this_run();
+
+        Ok(())
+    }
+}
+
+/// The observers of a single event type.
+struct ObserverList<E> {
+    global: Vec<Box<dyn ErasedObserver<E>>>,
+    entities: HashMap<EntityId, Vec<Box<dyn ErasedObserver<E>>>>,
+}
+
+impl<E> ObserverList<E> {
+    fn new() -> Self {
+        Self {
+            global: Vec::new(),
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Runs the observers of the current target, followed by the global
+    /// observers. Returns the first error encountered, but always runs
+    /// every observer.
+    fn run(&mut self, trigger: &mut Trigger<E>, world: &World) -> Result<(), RetrievalError> {
+        let mut result = Ok(());
+
+        let targeted = self
+            .entities
+            .get_mut(&trigger.target().id())
+            .map(|observers| observers.iter_mut())
+            .into_iter()
+            .flatten();
+
+        for observer in targeted.chain(self.global.iter_mut()) {
+            let next = observer.run(trigger, world);
+            result = result.and(next);
+        }
+
+        result
+    }
+}
+
+/// Every observer registered with a [`World`], by event type.
+#[derive(Default)]
+pub(crate) struct Observers {
+    lists: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Observers {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn add<E, O>(
+        &mut self,
+        entity: Option<Entity>,
+        observer: O,
+    ) -> Result<(), AccessError>
+    where
+        E: 'static,
+        O: for<'a> Observer<'a, E> + Send + Sync + 'static,
+    {
+        validate::<E, O>()?;
+
+        let observer: Box<dyn ErasedObserver<E>> = Box::new(ObserverState::new(observer));
+        let list = self.list_mut::<E>();
+
+        match entity {
+            Some(entity) => list.entities.entry(entity.id()).or_default().push(observer),
+            None => list.global.push(observer),
+        }
+
+        Ok(())
+    }
+
+    /// Runs the observers of `event`, starting at `entity` and moving up
+    /// the parent chain for as long as the trigger propagates.
+    pub(crate) fn trigger<E: 'static>(
+        world: &mut World,
+        event: E,
+        entity: Entity,
+        propagate: bool,
+    ) -> Result<(), RetrievalError> {
+        // The observers are taken out of the world while they run, so
+        // they can borrow the world alongside themselves.
+        let list = match world.observers_mut().take::<E>() {
+            Some(list) => list,
+            None => return Ok(()),
+        };
+
+        let mut guard = ListGuard {
+            world: &mut *world,
+            list: ManuallyDrop::new(list),
+        };
+        let ListGuard {
+            world: observed,
+            list,
+        } = &mut guard;
+
+        let mut trigger = Trigger::new(event, entity, propagate);
+        let mut visited = Vec::new();
+        let mut result = Ok(());
+
+        loop {
+            let next = list.run(&mut trigger, observed);
+            result = result.and(next);
+
+            if !trigger.is_propagating() {
+                break;
+            }
+
+            visited.push(trigger.target);
+
+            match observed.parent_of(trigger.target) {
+                Some(parent) if !visited.contains(&parent) => trigger.target = parent,
+                _ => break,
+            }
+        }
+
+        drop(guard);
+        world.apply_commands();
+
+        result
+    }
+
+    fn list_mut<E: 'static>(&mut self) -> &mut ObserverList<E> {
+        self.lists
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(ObserverList::<E>::new()))
+            .downcast_mut()
+            .unwrap_or_else(|| unsafe {
+                utils::debug_unreachable("Observer list was stored under the wrong type.")
+            })
+    }
+
+    fn take<E: 'static>(&mut self) -> Option<Box<ObserverList<E>>> {
+        self.lists.remove(&TypeId::of::<E>()).map(|list| {
+            list.downcast().unwrap_or_else(|_| unsafe {
+                utils::debug_unreachable("Observer list was stored under the wrong type.")
+            })
+        })
+    }
+
+    fn restore<E: 'static>(&mut self, list: Box<ObserverList<E>>) {
+        self.lists.insert(TypeId::of::<E>(), list);
+    }
+}
+
+/// Returns the observers taken out by [`Observers::trigger`] when the
+/// trigger ends, including when it ends by unwinding.
+struct ListGuard<'w, E: 'static> {
+    world: &'w mut World,
+    list: ManuallyDrop<Box<ObserverList<E>>>,
+}
+
+impl<E: 'static> Drop for ListGuard<'_, E> {
+    fn drop(&mut self) {
+        // SAFETY: the list is never used again after it is taken.
+        let list = unsafe { ManuallyDrop::take(&mut self.list) };
+        self.world.observers_mut().restore(list);
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field("event_types", &self.lists.len())
+            .finish()
+    }
+}
+
+/// Checks that the observer never borrows the same resource or
+/// component mutably alongside another borrow of it.
+fn validate<E, O>() -> Result<(), AccessError>
+where
+    O: for<'a> Observer<'a, E>,
+{
+    let mut access = SystemAccess::default();
+    <O::Resources as ResourceData<'_>>::access(&mut access.resources);
+    <O::Components as ComponentData<'_>>::access(&mut access.components);
+
+    access.validate(any::type_name::<O>())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use crate::{hierarchy::Parent, storage::WriteResource};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Ping;
+
+    #[derive(Debug, Default)]
+    struct Visits(Vec<Entity>);
+
+    #[derive(Debug)]
+    struct Node;
+
+    /// Records every entity the event reaches, and stops it at `stop`.
+    struct Record {
+        stop: Option<Entity>,
+    }
+
+    impl<'a> Observer<'a, Ping> for Record {
+        type Resources = (WriteResource<'a, Visits>,);
+        type Components = ();
+
+        fn observe(&mut self, trigger: &mut Trigger<Ping>, (mut visits,): Self::Resources, _: ()) {
+            visits.0.push(trigger.target());
+
+            if Some(trigger.target()) == self.stop {
+                trigger.propagate(false);
+            }
+        }
+    }
+
+    struct Fail;
+
+    impl<'a> Observer<'a, Ping> for Fail {
+        type Resources = ();
+        type Components = ();
+
+        fn observe(&mut self, _: &mut Trigger<Ping>, _: (), _: ()) {
+            panic!("observer failed");
+        }
+    }
+
+    fn visits(world: &World) -> Vec<Entity> {
+        world.get_resource::<Visits>().unwrap().0.clone()
+    }
+
+    /// Creates a chain of entities, each the parent of the one before.
+    fn chain(world: &mut World, len: usize) -> Vec<Entity> {
+        let mut entities = vec![world.create_entity(Node)];
+
+        for i in 1..len {
+            let parent = world.create_entity(Node);
+            world
+                .add_components(entities[i - 1], Parent::new(parent))
+                .unwrap();
+            entities.push(parent);
+        }
+
+        entities
+    }
+
+    #[test]
+    fn triggers_observers() {
+        let mut world = World::new();
+        world.insert_resource(Visits::default());
+        let entities = chain(&mut world, 2);
+
+        world.observe(entities[0], Record { stop: None }).unwrap();
+        world.trigger(Ping, entities[1]).unwrap();
+        assert!(visits(&world).is_empty());
+
+        // Without propagation, the event stays at its target.
+        world.trigger(Ping, entities[0]).unwrap();
+        assert_eq!(visits(&world), [entities[0]]);
+
+        world.add_observer(Record { stop: None }).unwrap();
+        world.trigger(Ping, entities[1]).unwrap();
+        assert_eq!(visits(&world), [entities[0], entities[1]]);
+    }
+
+    #[test]
+    fn propagates_to_parents_until_stopped() {
+        let mut world = World::new();
+        world.insert_resource(Visits::default());
+        let entities = chain(&mut world, 3);
+
+        world
+            .add_observer(Record {
+                stop: Some(entities[1]),
+            })
+            .unwrap();
+        world.trigger_propagating(Ping, entities[0]).unwrap();
+        assert_eq!(visits(&world), &entities[..2]);
+    }
+
+    #[test]
+    fn propagation_ends_at_cycles() {
+        let mut world = World::new();
+        world.insert_resource(Visits::default());
+        let entities = chain(&mut world, 2);
+        world
+            .add_components(entities[1], Parent::new(entities[0]))
+            .unwrap();
+
+        world.add_observer(Record { stop: None }).unwrap();
+        world.trigger_propagating(Ping, entities[0]).unwrap();
+        assert_eq!(visits(&world), entities);
+    }
+
+    #[test]
+    fn observers_survive_panics() {
+        let mut world = World::new();
+        world.insert_resource(Visits::default());
+        let entity = world.create_entity(Node);
+
+        world.add_observer(Record { stop: None }).unwrap();
+        world.observe(entity, Fail).unwrap();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| world.trigger(Ping, entity)));
+        assert!(result.is_err());
+
+        // Both observers are still registered.
+        let other = world.create_entity(Node);
+        world.trigger(Ping, other).unwrap();
+        assert_eq!(visits(&world), [other]);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| world.trigger(Ping, entity))).is_err());
+    }
+}
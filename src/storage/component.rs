@@ -1,25 +1,75 @@
 use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 use std::{
-    any::{self, TypeId},
+    alloc::Layout,
+    any::{self, Any, TypeId},
+    borrow::Cow,
     collections::{hash_map::Entry, HashMap},
+    convert::TryFrom,
     mem,
+    ptr::NonNull,
 };
 
+use parking_lot::Mutex;
+
 use crate::{
+    archetype::ArchetypeId,
     cell::{AtomicRef, AtomicRefCell, AtomicRefMut},
-    component::Component,
+    change::{ChangeTicks, Mut},
+    command::Commands,
+    component::{
+        Component, ComponentFns, ComponentId, ComponentRegistry, ComponentSet, DropFn,
+        SerializeComponent,
+    },
     entity::Entity,
     entity::EntityId,
+    hook::ComponentHooks,
+    storage::{
+        sparse::{RawSparseArray, SparseArray},
+        tag::{self, TagSet},
+        DynamicStorage,
+    },
     utils,
 };
 
-type ComponentDropFn = unsafe fn(*mut ComponentStorageBytes, Entity) -> bool;
+/// A `ComponentStorage<T>` with its component type erased.
+type ErasedStorage = Box<dyn Any + Send + Sync>;
+
+type ComponentDropFn = fn(&mut ErasedStorage, Entity, u64) -> bool;
+type ComponentMoveFn = fn(&mut ErasedStorage, Entity, ArchetypeId);
+type ComponentUpdateFn = fn(&mut ErasedStorage);
+type ComponentPartsFn = for<'a> fn(&'a mut ErasedStorage) -> ErasedParts<'a>;
+
+/// The type-erased operations the allocator performs on every storage.
+#[derive(Copy, Clone)]
+struct StorageFns {
+    drop: ComponentDropFn,
+    move_to: ComponentMoveFn,
+    update_removed: ComponentUpdateFn,
+    parts: ComponentPartsFn,
+}
+
+impl fmt::Debug for StorageFns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StorageFns")
+            .field("drop", &(self.drop as *const ()))
+            .field("move_to", &(self.move_to as *const ()))
+            .field("update_removed", &(self.update_removed as *const ()))
+            .field("parts", &(self.parts as *const ()))
+            .finish()
+    }
+}
 
 /// A container for a dynamic storage type.
 #[derive(Debug)]
 pub struct ComponentStorageAllocator {
-    inner: HashMap<TypeId, AtomicRefCell<(ComponentStorageBytes, ComponentDropFn)>>,
+    inner: HashMap<TypeId, AtomicRefCell<(ErasedStorage, StorageFns)>>,
+    // The commands pushed by component hooks, shared with every storage
+    // registered with the allocator.
+    commands: Arc<Mutex<Commands>>,
+    registry: ComponentRegistry,
+    dynamic: HashMap<ComponentId, AtomicRefCell<DynamicStorage>>,
 }
 
 impl ComponentStorageAllocator {
@@ -27,12 +77,18 @@ impl ComponentStorageAllocator {
     pub fn new() -> Self {
         Self {
             inner: HashMap::new(),
+            commands: Arc::default(),
+            registry: ComponentRegistry::new(),
+            dynamic: HashMap::new(),
         }
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             inner: HashMap::with_capacity(capacity),
+            commands: Arc::default(),
+            registry: ComponentRegistry::new(),
+            dynamic: HashMap::new(),
         }
     }
 
@@ -47,9 +103,10 @@ impl ComponentStorageAllocator {
     /// Registers a component type with the `StorageContainer` using
     /// the value provided by the passed closure. This can be used
     /// to call custom constructors for the specific storage type
-    /// being used. Returns a bool indicating whether the storage
-    /// was registered. If this method returns `false`, it means
-    /// that the storage was already registered.
+    /// being used, or to register [`ComponentHooks`] with
+    /// [`ComponentStorage::with_hooks`]. Returns a bool indicating
+    /// whether the storage was registered. If this method returns
+    /// `false`, it means that the storage was already registered.
     pub fn register_with<T: Component, F>(&mut self, f: F) -> bool
     where
         F: FnOnce() -> ComponentStorage<T>,
@@ -61,16 +118,100 @@ impl ComponentStorageAllocator {
         match self.inner.entry(type_id) {
             Occupied(_) => false,
             Vacant(v) => {
-                let storage = f();
-
-                let drop_fn = ComponentStorage::<T>::drop_component;
-                let bytes = ComponentStorageBytes::new(storage);
-                v.insert(AtomicRefCell::new((bytes, drop_fn)));
+                let mut storage = f();
+                storage.commands = Arc::clone(&self.commands);
+                self.registry.register::<T>(storage.kind(), storage.fns);
+
+                let fns = StorageFns {
+                    drop: ComponentStorage::<T>::drop_component,
+                    move_to: ComponentStorage::<T>::move_component,
+                    update_removed: ComponentStorage::<T>::update_removed_erased,
+                    parts: ComponentStorage::<T>::erased_parts,
+                };
+                let storage: ErasedStorage = Box::new(storage);
+                v.insert(AtomicRefCell::new((storage, fns)));
                 true
             }
         }
     }
 
+    /// The component types seen by the allocator, along with the info of
+    /// every type which had its storage registered.
+    pub fn registry(&self) -> &ComponentRegistry {
+        &self.registry
+    }
+
+    pub(crate) fn registry_mut(&mut self) -> &mut ComponentRegistry {
+        &mut self.registry
+    }
+
+    /// Registers a component with no Rust type, which is stored as raw
+    /// bytes of the given layout and dropped with `drop`, if any. Every
+    /// call registers a new component, even if the name was already
+    /// used.
+    pub fn register_dynamic<N>(
+        &mut self,
+        name: N,
+        layout: Layout,
+        drop: Option<DropFn>,
+    ) -> ComponentId
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        let id = self.registry.register_dynamic(name.into(), layout, drop);
+        let storage = DynamicStorage::new(id, layout, drop);
+        self.dynamic.insert(id, AtomicRefCell::new(storage));
+        id
+    }
+
+    /// Retrieves the storage of a dynamic component. Returns `None` if
+    /// the id isn't of a dynamic component.
+    pub fn get_dynamic(&self, id: ComponentId) -> Option<AtomicRef<'_, DynamicStorage>> {
+        self.dynamic.get(&id).map(AtomicRefCell::borrow)
+    }
+
+    pub fn get_dynamic_mut(&self, id: ComponentId) -> Option<AtomicRefMut<'_, DynamicStorage>> {
+        self.dynamic.get(&id).map(AtomicRefCell::borrow_mut)
+    }
+
+    /// Splits the storages of the components into their parts, in the
+    /// order of `components`, whether they are typed or dynamic. Every
+    /// storage is borrowed at once, so components of different storages
+    /// can be reached at the same time. Returns `None` if a component has
+    /// no storage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component is given more than once.
+    pub(crate) fn erased_parts(
+        &mut self,
+        components: &[ComponentId],
+    ) -> Option<Vec<ErasedParts<'_>>> {
+        let duplicate = (1..components.len()).find(|&i| components[..i].contains(&components[i]));
+        if let Some(i) = duplicate {
+            panic!("Component {} was split more than once.", components[i]);
+        }
+
+        let position = |component| components.iter().position(|&other| other == component);
+        let mut parts: Vec<_> = components.iter().map(|_| None).collect();
+
+        let registry = &self.registry;
+        for (&type_id, cell) in self.inner.iter_mut() {
+            if let Some(index) = registry.get_type_id(type_id).and_then(position) {
+                let (storage, fns) = cell.get_mut();
+                parts[index] = Some((fns.parts)(storage));
+            }
+        }
+
+        for (&component, cell) in self.dynamic.iter_mut() {
+            if let Some(index) = position(component) {
+                parts[index] = Some(cell.get_mut().erased_parts());
+            }
+        }
+
+        parts.into_iter().collect()
+    }
+
     pub fn contains<T: Component>(&self) -> bool {
         let type_id = TypeId::of::<T>();
         self.inner.contains_key(&type_id)
@@ -82,18 +223,19 @@ impl ComponentStorageAllocator {
     pub fn get<T: Component>(&self) -> Option<AtomicRef<'_, ComponentStorage<T>>> {
         self.inner
             .get(&TypeId::of::<T>())
-            .map(|cell| AtomicRef::map(cell.borrow(), |(bytes, _)| unsafe { bytes.cast() }))
+            .map(|cell| AtomicRef::map(cell.borrow(), |(storage, _)| cast(storage)))
     }
 
     pub fn try_get<T: Component>(&self) -> Option<AtomicRef<'_, ComponentStorage<T>>> {
         self.inner
             .get(&TypeId::of::<T>())
-            .and_then(|cell| match cell.try_borrow() {
-                Some(borrow) => Some(AtomicRef::map(borrow, |(bytes, _)| unsafe { bytes.cast() })),
-                None => None,
-            })
+            .and_then(|cell| cell.try_borrow())
+            .map(|borrow| AtomicRef::map(borrow, |(storage, _)| cast(storage)))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn get_unchecked<T: Component>(&self) -> AtomicRef<'_, ComponentStorage<T>> {
         let cell = self.inner.get(&TypeId::of::<T>()).unwrap_or_else(|| {
             utils::debug_closure(|| {
@@ -104,9 +246,12 @@ impl ComponentStorageAllocator {
             })
         });
 
-        AtomicRef::map(cell.borrow(), |(bytes, _)| bytes.cast())
+        AtomicRef::map(cell.borrow(), |(storage, _)| cast(storage))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn try_get_unchecked<T: Component>(
         &self,
     ) -> Option<AtomicRef<'_, ComponentStorage<T>>> {
@@ -119,32 +264,29 @@ impl ComponentStorageAllocator {
             })
         });
 
-        match cell.try_borrow() {
-            Some(borrow) => AtomicRef::map(borrow, |(bytes, _)| bytes.cast()).into(),
-            None => None,
-        }
+        cell.try_borrow()
+            .map(|borrow| AtomicRef::map(borrow, |(storage, _)| cast(storage)))
     }
 
     /// Retrieves a mutable reference to the storage associated with
     /// the component type. Returns `None` if no storage was registered
     /// for the component.
     pub fn get_mut<T: Component>(&self) -> Option<AtomicRefMut<'_, ComponentStorage<T>>> {
-        self.inner.get(&TypeId::of::<T>()).map(|cell| {
-            AtomicRefMut::map(cell.borrow_mut(), |(bytes, _)| unsafe { bytes.cast_mut() })
-        })
+        self.inner
+            .get(&TypeId::of::<T>())
+            .map(|cell| AtomicRefMut::map(cell.borrow_mut(), |(storage, _)| cast_mut(storage)))
     }
 
     pub fn try_get_mut<T: Component>(&self) -> Option<AtomicRefMut<'_, ComponentStorage<T>>> {
         self.inner
             .get(&TypeId::of::<T>())
-            .and_then(|cell| match cell.try_borrow_mut() {
-                Some(borrow) => {
-                    AtomicRefMut::map(borrow, |(bytes, _)| unsafe { bytes.cast_mut() }).into()
-                }
-                None => None,
-            })
+            .and_then(|cell| cell.try_borrow_mut())
+            .map(|borrow| AtomicRefMut::map(borrow, |(storage, _)| cast_mut(storage)))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn get_mut_unchecked<T: Component>(&self) -> AtomicRefMut<'_, ComponentStorage<T>> {
         let cell = self.inner.get(&TypeId::of::<T>()).unwrap_or_else(|| {
             utils::debug_closure(|| {
@@ -155,9 +297,12 @@ impl ComponentStorageAllocator {
             })
         });
 
-        AtomicRefMut::map(cell.borrow_mut(), |(bytes, _)| bytes.cast_mut())
+        AtomicRefMut::map(cell.borrow_mut(), |(storage, _)| cast_mut(storage))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn try_get_mut_unchecked<T: Component>(
         &self,
     ) -> Option<AtomicRefMut<'_, ComponentStorage<T>>> {
@@ -170,10 +315,8 @@ impl ComponentStorageAllocator {
             })
         });
 
-        match cell.try_borrow_mut() {
-            Some(borrow) => AtomicRefMut::map(borrow, |(bytes, _)| bytes.cast_mut()).into(),
-            None => None,
-        }
+        cell.try_borrow_mut()
+            .map(|borrow| AtomicRefMut::map(borrow, |(storage, _)| cast_mut(storage)))
     }
 
     pub fn get_or_register<T: Component>(&mut self) -> AtomicRef<'_, ComponentStorage<T>> {
@@ -234,14 +377,62 @@ impl ComponentStorageAllocator {
     pub fn remove_storage<T: Component>(&mut self) -> Option<ComponentStorage<T>> {
         self.inner
             .remove(&TypeId::of::<T>())
-            .map(|cell| unsafe { cell.into_inner().0.into_storage() })
+            .map(|cell| into_storage(cell.into_inner().0))
     }
 
-    pub fn remove_components(&mut self, entity: Entity) {
+    /// Removes every component of the entity, recording each removal in
+    /// the removal log of its storage at `change_tick`.
+    ///
+    /// The allocator doesn't keep a change tick of its own, so callers
+    /// pass the current tick of the world, as returned by
+    /// [`World::change_tick`](crate::World::change_tick). Before the
+    /// tick was a parameter, every removal was logged at tick `0`.
+    pub fn remove_components(&mut self, entity: Entity, change_tick: u64) {
         self.inner.values_mut().for_each(|cell| {
-            let (bytes, drop_fn) = cell.get_mut();
-            unsafe {
-                drop_fn(bytes, entity);
+            let (storage, fns) = cell.get_mut();
+            (fns.drop)(storage, entity, change_tick);
+        });
+
+        self.dynamic.values_mut().for_each(|cell| {
+            cell.get_mut().remove(entity.id());
+        });
+    }
+
+    /// Moves the given components of the entity into the column of
+    /// `archetype`, if they are stored in a table. Only the storages of
+    /// `components` are visited.
+    pub fn move_entity(
+        &mut self,
+        entity: Entity,
+        components: &ComponentSet,
+        archetype: ArchetypeId,
+    ) {
+        for component in components.iter() {
+            let cell = match self.registry.type_id(component) {
+                Some(type_id) => self.inner.get_mut(&type_id),
+                // Dynamic components are never stored in tables.
+                None => continue,
+            };
+
+            if let Some(cell) = cell {
+                let (storage, fns) = cell.get_mut();
+                (fns.move_to)(storage, entity, archetype);
+            }
+        }
+    }
+
+    /// Takes every command pushed by component hooks so far.
+    pub fn take_commands(&self) -> Commands {
+        self.commands.lock().take()
+    }
+
+    /// Advances the removal log of every storage which isn't currently
+    /// borrowed. See [`ComponentStorage::update_removed`].
+    pub fn update_removed(&self) {
+        self.inner.values().for_each(|cell| {
+            if let Some(mut borrow) = cell.try_borrow_mut() {
+                let (storage, fns) = &mut *borrow;
+                (fns.update_removed)(storage);
             }
         })
     }
@@ -262,6 +453,9 @@ impl ComponentStorageAllocator {
         }
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn get_and_update_unchecked<T: Component, F>(&self, f: F)
     where
         F: FnOnce(&mut ComponentStorage<T>),
@@ -270,155 +464,920 @@ impl ComponentStorageAllocator {
     }
 }
 
-const COMP_STORAGE_BYTES: usize = mem::size_of::<ComponentStorage<()>>();
-
-// Important implementation note: this type relies
-// on the internal representation of ComponentStorage<T>,
-// which has a size of 48 and an alignment of 8.
-// This means a transmute between these two types
-// *should* be safe assuming T is the correct type
-// when transmuting back to the ComponentStorage<T>.
-#[repr(C, align(8))]
-pub struct ComponentStorageBytes {
-    bytes: [u8; COMP_STORAGE_BYTES],
+impl Default for ComponentStorageAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl ComponentStorageBytes {
-    pub fn new<T: Component>(storage: ComponentStorage<T>) -> Self {
-        unsafe {
-            // SAFETY: ComponentStorage<T> and StorageBytes both
-            // have the same size and alignment, so this is just
-            // a direct conversion to the raw bytes of the storage.
-            mem::transmute(storage)
-        }
-    }
+fn cast<T: Component>(storage: &ErasedStorage) -> &ComponentStorage<T> {
+    storage.downcast_ref().unwrap_or_else(|| unsafe {
+        utils::debug_unreachable("Component storage was stored under the wrong type.")
+    })
+}
 
-    pub unsafe fn cast<T: Component>(&self) -> &ComponentStorage<T> {
-        mem::transmute(self)
-    }
+fn cast_mut<T: Component>(storage: &mut ErasedStorage) -> &mut ComponentStorage<T> {
+    storage.downcast_mut().unwrap_or_else(|| unsafe {
+        utils::debug_unreachable("Component storage was stored under the wrong type.")
+    })
+}
 
-    pub unsafe fn cast_mut<T: Component>(&mut self) -> &mut ComponentStorage<T> {
-        mem::transmute(self)
-    }
+fn into_storage<T: Component>(storage: ErasedStorage) -> ComponentStorage<T> {
+    *storage.downcast().unwrap_or_else(|_| unsafe {
+        utils::debug_unreachable("Component storage was stored under the wrong type.")
+    })
+}
 
-    pub unsafe fn into_storage<T: Component>(self) -> ComponentStorage<T> {
-        mem::transmute(self)
-    }
+/// How a [`ComponentStorage`] lays out and finds its components.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum StorageKind {
+    /// One column per archetype, so components of the same archetype
+    /// can be iterated in lockstep. Best for components which are
+    /// iterated often and rarely added or removed.
+    Table,
+    /// A single column in the order components were added, indexed like
+    /// a sparse set. Removing a component shifts the ones after it down,
+    /// so their order is kept, which costs time linear in the length of
+    /// the storage. The default for types which aren't zero-sized.
+    #[default]
+    Ordered,
+    /// A single column, indexed by entity ID. Adding and removing
+    /// components is cheap, but removing one moves the last component of
+    /// the column into its place, so the order of the column changes.
+    SparseSet,
+    /// A single column, indexed by a hash map. Uses less memory than a
+    /// sparse set for components held by few entities.
+    HashMap,
+    /// A bitset of the entities holding the component. Only for
+    /// zero-sized marker components, and used for them by default. The
+    /// components are iterated in ascending order of entity ID.
+    Null,
 }
 
-impl fmt::Debug for ComponentStorageBytes {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_list().entries(&self.bytes[..]).finish()
+impl StorageKind {
+    /// The kind of storage used for `T` unless another one is chosen:
+    /// [`Null`](Self::Null) for zero-sized types, and
+    /// [`Ordered`](Self::Ordered) for every other type.
+    pub fn default_for<T>() -> Self {
+        if mem::size_of::<T>() == 0 {
+            Self::Null
+        } else {
+            Self::Ordered
+        }
     }
 }
 
-#[repr(C)]
 #[derive(Debug)]
 pub struct ComponentStorage<T: Component> {
-    ids: Vec<EntityId>,
-    comps: Vec<T>,
+    // A null storage keeps its components in `tags`, and has no columns.
+    // Every other kind of storage but a table has a single column.
+    columns: Vec<Column<T>>,
+    index: Index,
+    tags: TagSet<T>,
+    // The tick recorded for components added or changed through this
+    // storage, set whenever the storage is borrowed for writing.
+    change_tick: u64,
+    // Entities which lost their component, along with the tick they lost
+    // it at. Removals are kept for two updates of the log.
+    removed: Vec<(EntityId, u64)>,
+    removed_previous: Vec<(EntityId, u64)>,
+    hooks: ComponentHooks,
+    fns: ComponentFns,
+    commands: Arc<Mutex<Commands>>,
 }
 
 impl<T: Component> ComponentStorage<T> {
     pub fn new() -> Self {
-        Self {
-            ids: Vec::new(),
-            comps: Vec::new(),
-        }
+        Self::with_capacity(0)
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_kind_and_capacity(StorageKind::default_for::<T>(), capacity)
+    }
+
+    /// Constructs a storage of the given kind. Pass a closure calling
+    /// this to [`ComponentStorageAllocator::register_with`] to choose how
+    /// a component type is stored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `kind` is [`StorageKind::Null`] and `T` is not zero-sized.
+    pub fn with_kind(kind: StorageKind) -> Self {
+        Self::with_kind_and_capacity(kind, 0)
+    }
+
+    pub fn with_kind_and_capacity(kind: StorageKind, capacity: usize) -> Self {
+        if kind == StorageKind::Null && mem::size_of::<T>() != 0 {
+            panic!(
+                "Component {} is not zero-sized, so it can't use a null storage.",
+                any::type_name::<T>(),
+            );
+        }
+
+        let columns = match kind {
+            StorageKind::Table | StorageKind::Null => Vec::new(),
+            _ => vec![Column::with_capacity(None, capacity)],
+        };
+
         Self {
-            ids: Vec::with_capacity(capacity),
-            comps: Vec::with_capacity(capacity),
+            columns,
+            index: Index::new(kind),
+            tags: TagSet::new(),
+            change_tick: 0,
+            removed: Vec::new(),
+            removed_previous: Vec::new(),
+            hooks: ComponentHooks::new(),
+            fns: ComponentFns::new(),
+            commands: Arc::default(),
         }
     }
 
+    /// Constructs a storage which keeps the components of each archetype
+    /// in their own column. Entities created through the [`World`] are
+    /// placed in the same row of every table column of their archetype,
+    /// which lets [`Join`] iterate several components in lockstep.
+    ///
+    /// Tables are opt-in, and only change how the components of a type
+    /// are laid out. Systems and queries still borrow whole storages, and
+    /// only a [`Join`] of those storages iterates them archetype by
+    /// archetype.
+    ///
+    /// [`World`]: crate::World
+    /// [`Join`]: crate::join::Join
+    pub fn table() -> Self {
+        Self::with_kind(StorageKind::Table)
+    }
+
+    pub fn kind(&self) -> StorageKind {
+        self.index.kind()
+    }
+
     pub fn len(&self) -> usize {
-        let len = self.comps.len();
-        debug_assert_eq!(
-            self.ids.len(),
-            len,
-            "ID & Component Vec lengths do not match."
-        );
+        self.columns.iter().map(Column::len).sum::<usize>() + self.tags.len()
+    }
 
-        len
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    /// Sets the tick recorded for components which are added or changed
+    /// through this storage from now on.
+    pub fn set_change_tick(&mut self, tick: u64) {
+        self.change_tick = tick;
+    }
+
+    /// Sets the hooks which run when components are added to or removed
+    /// from this storage.
+    pub fn with_hooks(mut self, hooks: ComponentHooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    pub fn hooks(&self) -> &ComponentHooks {
+        &self.hooks
+    }
+
+    /// Records how to clone the component in its
+    /// [`ComponentInfo`](crate::component::ComponentInfo) once the
+    /// storage is registered.
+    pub fn with_clone(mut self) -> Self
+    where
+        T: Clone,
+    {
+        self.fns = self.fns.with_clone::<T>();
+        self
+    }
+
+    /// Records how to serialize the component in its
+    /// [`ComponentInfo`](crate::component::ComponentInfo) once the
+    /// storage is registered.
+    pub fn with_serialize(mut self) -> Self
+    where
+        T: SerializeComponent,
+    {
+        self.fns = self.fns.with_serialize::<T>();
+        self
+    }
+
+    /// Records how to debug format the component in its
+    /// [`ComponentInfo`](crate::component::ComponentInfo) once the
+    /// storage is registered.
+    pub fn with_debug(mut self) -> Self
+    where
+        T: fmt::Debug,
+    {
+        self.fns = self.fns.with_debug::<T>();
+        self
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.tags.contains(id) || self.locate(id).is_some()
     }
 
     pub fn push(&mut self, id: EntityId, t: T) -> Result<(), T> {
-        if self.ids.contains(&id) {
-            Err(t)
+        self.push_in(None, id, t)
+    }
+
+    /// Pushes the component into the column of `archetype`. Storages
+    /// which aren't tables ignore the archetype.
+    pub(crate) fn push_in(
+        &mut self,
+        archetype: Option<ArchetypeId>,
+        id: EntityId,
+        t: T,
+    ) -> Result<(), T> {
+        if self.contains(id) {
+            return Err(t);
+        }
+
+        let ticks = ChangeTicks::new(self.change_tick);
+
+        if self.kind() == StorageKind::Null {
+            self.tags.insert(id, t, ticks);
         } else {
-            self.ids.push(id);
-            self.comps.push(t);
-            Ok(())
+            let column = self.column_for(archetype);
+            self.place(column, id, t, ticks);
         }
+
+        self.run_hooks(id, |hooks, entity, commands| {
+            hooks.run_add(entity, commands);
+            hooks.run_insert(entity, commands);
+        });
+
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> Option<(EntityId, T)> {
-        let id = self.ids.pop();
-        let comp = self.comps.pop();
+    /// Stores the component for the entity, replacing and returning the
+    /// component it already had, if any.
+    pub fn insert(&mut self, id: EntityId, t: T) -> Option<T> {
+        if self.tags.contains(id) {
+            self.run_hooks(id, |hooks, entity, commands| {
+                hooks.run_replace(entity, commands);
+            });
+
+            if let Some(ticks) = self.tags.ticks_mut(id) {
+                ticks.set_changed(self.change_tick);
+            }
+
+            self.run_hooks(id, |hooks, entity, commands| {
+                hooks.run_insert(entity, commands);
+            });
+
+            // Zero-sized components are interchangeable, so the new
+            // component is handed back in place of the stored one.
+            return Some(t);
+        }
+
+        match self.locate(id) {
+            Some((column, row)) => {
+                self.run_hooks(id, |hooks, entity, commands| {
+                    hooks.run_replace(entity, commands);
+                });
 
-        match (id, comp) {
-            (Some(id), Some(comp)) => Some((id, comp)),
-            (None, None) => None,
-            _ => unsafe {
-                utils::debug_unreachable(
-                    "Invalid ComponentStorage state. ID & Component Vec lengths do not match.",
-                )
-            },
+                let column = &mut self.columns[column];
+                let old = mem::replace(&mut column.comps[row], t);
+                column.ticks[row].set_changed(self.change_tick);
+
+                self.run_hooks(id, |hooks, entity, commands| {
+                    hooks.run_insert(entity, commands);
+                });
+
+                Some(old)
+            }
+            None => {
+                self.push(id, t).unwrap_or_else(|_| unsafe {
+                    utils::debug_unreachable("Component was found after it was not found.")
+                });
+
+                None
+            }
+        }
+    }
+
+    /// Moves the component of the entity into the column of `archetype`.
+    /// This does nothing for storages which aren't tables.
+    pub(crate) fn move_to(&mut self, id: EntityId, archetype: ArchetypeId) {
+        if self.kind() != StorageKind::Table {
+            return;
+        }
+
+        let (column, row) = match self.locate(id) {
+            Some(location) => location,
+            None => return,
+        };
+
+        if self.columns[column].archetype != Some(archetype) {
+            let (id, comp, ticks) = self.take(column, row);
+            let target = self.column_for(Some(archetype));
+            self.place(target, id, comp, ticks);
         }
     }
 
+    pub fn pop(&mut self) -> Option<(EntityId, T)> {
+        if let Some(id) = self.tags.ids().last() {
+            return self.remove_tag(id).map(|comp| (id, comp));
+        }
+
+        let column = self.columns.iter().rposition(|column| !column.is_empty())?;
+        let row = self.columns[column].len() - 1;
+
+        Some(self.remove_at(column, row))
+    }
+
+    /// Removes the component at `index`, counting through the columns
+    /// of the storage in iteration order. Takes time linear in the length
+    /// of the storage if it is [`StorageKind::Ordered`].
     pub fn remove(&mut self, index: usize) -> Option<(EntityId, T)> {
-        if index >= self.len() {
-            None
-        } else {
-            let id = self.ids.remove(index);
-            let comp = self.comps.remove(index);
+        let mut row = index;
 
-            Some((id, comp))
+        for column in 0..self.columns.len() {
+            let len = self.columns[column].len();
+
+            if row < len {
+                return Some(self.remove_at(column, row));
+            }
+
+            row -= len;
         }
+
+        let id = self.tags.ids().nth(row)?;
+        self.remove_tag(id).map(|comp| (id, comp))
     }
 
+    /// Removes the component of the entity. Takes time linear in the
+    /// length of the storage if it is [`StorageKind::Ordered`].
     pub fn remove_by_id(&mut self, id: EntityId) -> Option<T> {
-        self.ids
+        if self.tags.contains(id) {
+            return self.remove_tag(id);
+        }
+
+        self.locate(id)
+            .map(|(column, row)| self.remove_at(column, row).1)
+    }
+
+    /// Returns the entities which lost their component after `last_run`,
+    /// as long as the removal is still in the log.
+    pub fn removed_since(&self, last_run: u64) -> impl Iterator<Item = EntityId> + '_ {
+        self.removed_previous
             .iter()
-            .enumerate()
-            .find(|(_, &other_id)| id == other_id)
-            .map(|(index, _)| index)
-            .and_then(|index| {
-                self.ids.remove(index);
-                self.comps.remove(index).into()
-            })
+            .chain(self.removed.iter())
+            .filter(move |(_, tick)| *tick > last_run)
+            .map(|(id, _)| *id)
+    }
+
+    /// Advances the removal log, dropping the removals which were
+    /// already in the log during the previous update. The dispatcher does
+    /// this at the start of every tick, so a system sees every removal
+    /// as long as it runs at least once per tick.
+    pub fn update_removed(&mut self) {
+        mem::swap(&mut self.removed, &mut self.removed_previous);
+        self.removed.clear();
+    }
+
+    pub fn clear_removed(&mut self) {
+        self.removed.clear();
+        self.removed_previous.clear();
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        if self.tags.contains(id) {
+            return Some(tag::value_ref());
+        }
+
+        self.locate(id)
+            .map(|(column, row)| &self.columns[column].comps[row])
+    }
+
+    /// Mutably borrows the component of the entity, marking it as
+    /// changed if the returned reference is dereferenced mutably.
+    pub fn get_mut(&mut self, id: EntityId) -> Option<Mut<'_, T>> {
+        if self.tags.contains(id) {
+            let ticks = self.tags.ticks_mut(id)?;
+            return Some(Mut::new(tag::value_mut(), ticks, self.change_tick));
+        }
+
+        let (column, row) = self.locate(id)?;
+        let column = &mut self.columns[column];
+
+        Some(Mut::new(
+            &mut column.comps[row],
+            &mut column.ticks[row],
+            self.change_tick,
+        ))
+    }
+
+    /// Returns the ticks at which the component of the entity was added
+    /// and last changed.
+    pub fn ticks_of(&self, id: EntityId) -> Option<ChangeTicks> {
+        self.tags.ticks(id).or_else(|| {
+            self.locate(id)
+                .map(|(column, row)| self.columns[column].ticks[row])
+        })
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (EntityId, &'_ T)> {
-        self.ids.iter().copied().zip(self.comps.iter())
+        self.columns
+            .iter()
+            .flat_map(|column| column.ids.iter().copied().zip(column.comps.iter()))
+            .chain(self.tags.ids().map(|id| (id, tag::value_ref())))
     }
 
-    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &'_ mut T)> {
-        self.ids.iter().copied().zip(self.comps.iter_mut())
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, Mut<'_, T>)> {
+        let change_tick = self.change_tick;
+
+        let tags = self
+            .tags
+            .iter_mut()
+            .map(move |(id, ticks)| (id, Mut::new(tag::value_mut(), ticks, change_tick)));
+
+        self.columns
+            .iter_mut()
+            .flat_map(move |column| {
+                column
+                    .ids
+                    .iter()
+                    .copied()
+                    .zip(column.comps.iter_mut().zip(column.ticks.iter_mut()))
+                    .map(move |(id, (comp, ticks))| (id, Mut::new(comp, ticks, change_tick)))
+            })
+            .chain(tags)
+    }
+
+    pub fn iter_ticks(&self) -> impl Iterator<Item = (EntityId, &'_ T, ChangeTicks)> {
+        let columns = self.columns.iter().flat_map(|column| {
+            column
+                .ids
+                .iter()
+                .copied()
+                .zip(column.comps.iter().zip(column.ticks.iter().copied()))
+                .map(|(id, (comp, ticks))| (id, comp, ticks))
+        });
+        let tags = self
+            .tags
+            .iter()
+            .map(|(id, ticks)| (id, tag::value_ref(), ticks));
+
+        columns.chain(tags)
     }
 
     pub fn comp_iter(&self) -> impl Iterator<Item = &'_ T> {
-        self.comps.iter()
+        self.columns
+            .iter()
+            .flat_map(|column| column.comps.iter())
+            .chain(self.tags.ids().map(|_| tag::value_ref()))
     }
 
-    pub fn comp_iter_mut(&mut self) -> impl Iterator<Item = &'_ mut T> {
-        self.comps.iter_mut()
+    pub fn comp_iter_mut(&mut self) -> impl Iterator<Item = Mut<'_, T>> {
+        let change_tick = self.change_tick;
+
+        let tags = self
+            .tags
+            .iter_mut()
+            .map(move |(_, ticks)| Mut::new(tag::value_mut(), ticks, change_tick));
+
+        self.columns
+            .iter_mut()
+            .flat_map(move |column| {
+                column
+                    .comps
+                    .iter_mut()
+                    .zip(column.ticks.iter_mut())
+                    .map(move |(comp, ticks)| Mut::new(comp, ticks, change_tick))
+            })
+            .chain(tags)
     }
 
-    unsafe fn drop_component(ptr: *mut ComponentStorageBytes, entity: Entity) -> bool {
-        let storage = &mut *mem::transmute::<_, *mut Self>(ptr);
+    /// Splits the storage into its columns, for [`Join`] to borrow the
+    /// components of different entities mutably at the same time.
+    ///
+    /// [`Join`]: crate::join::Join
+    pub(crate) fn raw_parts(&mut self) -> RawParts<'_, T> {
+        let columns: Vec<_> = self
+            .columns
+            .iter_mut()
+            .map(|column| RawColumn {
+                archetype: column.archetype,
+                ids: &column.ids,
+                comps: column.comps.as_mut_ptr(),
+                ticks: column.ticks.as_mut_ptr(),
+            })
+            .collect();
+
+        let (tag_words, tag_ticks) = self.tags.raw_parts();
 
-        match storage.remove_by_id(entity.id()) {
-            Some(_) => true,
-            None => false,
+        RawParts {
+            columns,
+            locator: self.index.locator(),
+            tag_words,
+            tag_ticks,
+            change_tick: self.change_tick,
+        }
+    }
+
+    pub(crate) fn locator(&self) -> Locator<'_> {
+        self.index.locator()
+    }
+
+    /// The bitset of the entities in a null storage.
+    pub(crate) fn tag_words(&self) -> &[u64] {
+        self.tags.words()
+    }
+
+    /// The column of every archetype, or the only column of a storage
+    /// which isn't a table.
+    pub(crate) fn columns(&self) -> impl Iterator<Item = ColumnRef<'_, T>> {
+        self.columns.iter().map(|column| ColumnRef {
+            archetype: column.archetype,
+            ids: &column.ids,
+            comps: &column.comps,
+        })
+    }
+
+    fn locate(&self, id: EntityId) -> Option<(usize, usize)> {
+        self.locator().locate(id)
+    }
+
+    /// Returns the index of the column of `archetype`, creating the
+    /// column if it doesn't exist yet.
+    fn column_for(&mut self, archetype: Option<ArchetypeId>) -> usize {
+        let table = match &mut self.index {
+            Index::Table(table) => table,
+            _ => return 0,
+        };
+        let columns = &mut self.columns;
+
+        *table.columns.entry(archetype).or_insert_with(|| {
+            columns.push(Column::with_capacity(archetype, 0));
+            columns.len() - 1
+        })
+    }
+
+    fn place(&mut self, column: usize, id: EntityId, t: T, ticks: ChangeTicks) {
+        let target = &mut self.columns[column];
+        target.push(id, t, ticks);
+        self.index.insert(id, column, target.len() - 1);
+    }
+
+    /// Takes the component at the row out of the column without running
+    /// any hooks. The row is filled with the last component of the
+    /// column, unless the storage keeps its order, in which case every
+    /// later row is shifted down and indexed again. That is linear in
+    /// the length of the column, which is the price of
+    /// [`StorageKind::Ordered`].
+    fn take(&mut self, column: usize, row: usize) -> (EntityId, T, ChangeTicks) {
+        let target = &mut self.columns[column];
+
+        if let Index::Ordered(sparse) = &mut self.index {
+            let taken = target.remove(row);
+            sparse.remove(taken.0);
+
+            // Every component after the removed one moved down a row.
+            for (row, &id) in target.ids.iter().enumerate().skip(row) {
+                sparse.insert(id, row);
+            }
+
+            return taken;
+        }
+
+        let taken = target.swap_remove(row);
+        self.index.remove(taken.0);
+
+        if let Some(&moved) = target.ids.get(row) {
+            self.index.insert(moved, column, row);
+        }
+
+        taken
+    }
+
+    fn remove_tag(&mut self, id: EntityId) -> Option<T> {
+        if !self.tags.contains(id) {
+            return None;
+        }
+
+        self.run_removal_hooks(id);
+
+        let (comp, _) = self.tags.remove(id)?;
+        self.removed.push((id, self.change_tick));
+
+        Some(comp)
+    }
+
+    fn remove_at(&mut self, column: usize, row: usize) -> (EntityId, T) {
+        self.run_removal_hooks(self.columns[column].ids[row]);
+
+        let (id, comp, _) = self.take(column, row);
+        self.removed.push((id, self.change_tick));
+
+        (id, comp)
+    }
+
+    fn run_hooks<F>(&self, id: EntityId, f: F)
+    where
+        F: FnOnce(&ComponentHooks, Entity, &mut Commands),
+    {
+        // Avoids locking the command queue for the common case of a
+        // component type without hooks.
+        if !self.hooks.is_empty() {
+            f(&self.hooks, Entity::new(id), &mut self.commands.lock());
+        }
+    }
+
+    fn run_removal_hooks(&self, id: EntityId) {
+        self.run_hooks(id, |hooks, entity, commands| {
+            hooks.run_replace(entity, commands);
+            hooks.run_remove(entity, commands);
+        });
+    }
+
+    fn drop_component(storage: &mut ErasedStorage, entity: Entity, change_tick: u64) -> bool {
+        let storage = cast_mut::<T>(storage);
+
+        storage.set_change_tick(change_tick);
+        storage.remove_by_id(entity.id()).is_some()
+    }
+
+    fn move_component(storage: &mut ErasedStorage, entity: Entity, archetype: ArchetypeId) {
+        cast_mut::<T>(storage).move_to(entity.id(), archetype);
+    }
+
+    fn update_removed_erased(storage: &mut ErasedStorage) {
+        cast_mut::<T>(storage).update_removed();
+    }
+
+    fn erased_parts(storage: &mut ErasedStorage) -> ErasedParts<'_> {
+        let parts = cast_mut::<T>(storage).raw_parts();
+
+        ErasedParts {
+            columns: parts
+                .columns
+                .iter()
+                .map(|column| (column.comps.cast::<u8>(), column.ticks))
+                .collect(),
+            size: mem::size_of::<T>(),
+            locator: parts.locator,
+            tags: Some((parts.tag_words, parts.tag_ticks)),
+            tag_value: NonNull::<T>::dangling().as_ptr().cast::<u8>(),
         }
     }
 }
 
+/// The components of a single archetype, or every component of a
+/// storage which isn't a table.
+#[derive(Debug)]
+struct Column<T> {
+    archetype: Option<ArchetypeId>,
+    ids: Vec<EntityId>,
+    comps: Vec<T>,
+    ticks: Vec<ChangeTicks>,
+}
+
+impl<T> Column<T> {
+    fn with_capacity(archetype: Option<ArchetypeId>, capacity: usize) -> Self {
+        Self {
+            archetype,
+            ids: Vec::with_capacity(capacity),
+            comps: Vec::with_capacity(capacity),
+            ticks: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn len(&self) -> usize {
+        let len = self.comps.len();
+        debug_assert_eq!(
+            self.ids.len(),
+            len,
+            "ID & Component Vec lengths do not match."
+        );
+
+        len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&mut self, id: EntityId, t: T, ticks: ChangeTicks) {
+        self.ids.push(id);
+        self.comps.push(t);
+        self.ticks.push(ticks);
+    }
+
+    fn remove(&mut self, row: usize) -> (EntityId, T, ChangeTicks) {
+        (
+            self.ids.remove(row),
+            self.comps.remove(row),
+            self.ticks.remove(row),
+        )
+    }
+
+    fn swap_remove(&mut self, row: usize) -> (EntityId, T, ChangeTicks) {
+        (
+            self.ids.swap_remove(row),
+            self.comps.swap_remove(row),
+            self.ticks.swap_remove(row),
+        )
+    }
+}
+
+/// Where the components of a table storage are.
+#[derive(Default, Debug)]
+pub(crate) struct TableIndex {
+    columns: HashMap<Option<ArchetypeId>, usize>,
+    locations: HashMap<EntityId, (usize, usize)>,
+}
+
+/// Maps entities to their rows, depending on the kind of the storage.
+#[derive(Debug)]
+enum Index {
+    Table(TableIndex),
+    Ordered(SparseArray<usize>),
+    SparseSet(SparseArray<usize>),
+    HashMap(HashMap<EntityId, usize>),
+    Null,
+}
+
+impl Index {
+    fn new(kind: StorageKind) -> Self {
+        match kind {
+            StorageKind::Table => Self::Table(TableIndex::default()),
+            StorageKind::Ordered => Self::Ordered(SparseArray::new()),
+            StorageKind::SparseSet => Self::SparseSet(SparseArray::new()),
+            StorageKind::HashMap => Self::HashMap(HashMap::new()),
+            StorageKind::Null => Self::Null,
+        }
+    }
+
+    fn kind(&self) -> StorageKind {
+        match self {
+            Self::Table(_) => StorageKind::Table,
+            Self::Ordered(_) => StorageKind::Ordered,
+            Self::SparseSet(_) => StorageKind::SparseSet,
+            Self::HashMap(_) => StorageKind::HashMap,
+            Self::Null => StorageKind::Null,
+        }
+    }
+
+    fn insert(&mut self, id: EntityId, column: usize, row: usize) {
+        match self {
+            Self::Table(table) => {
+                table.locations.insert(id, (column, row));
+            }
+            Self::Ordered(sparse) | Self::SparseSet(sparse) => {
+                sparse.insert(id, row);
+            }
+            Self::HashMap(map) => {
+                map.insert(id, row);
+            }
+            Self::Null => {}
+        }
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        match self {
+            Self::Table(table) => {
+                table.locations.remove(&id);
+            }
+            Self::Ordered(sparse) | Self::SparseSet(sparse) => {
+                sparse.remove(id);
+            }
+            Self::HashMap(map) => {
+                map.remove(&id);
+            }
+            Self::Null => {}
+        }
+    }
+
+    fn locator(&self) -> Locator<'_> {
+        match self {
+            Self::Table(table) => Locator::Table(table),
+            Self::Ordered(sparse) | Self::SparseSet(sparse) => Locator::SparseSet(sparse),
+            Self::HashMap(map) => Locator::HashMap(map),
+            Self::Null => Locator::Null,
+        }
+    }
+}
+
+pub(super) fn sparse_index(id: EntityId) -> usize {
+    usize::try_from(id).unwrap_or_else(|_| {
+        panic!("Entity {} does not fit in the index of a sparse set.", id);
+    })
+}
+
+/// Finds the column and row of the component of an entity.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Locator<'a> {
+    Table(&'a TableIndex),
+    SparseSet(&'a SparseArray<usize>),
+    HashMap(&'a HashMap<EntityId, usize>),
+    // A null storage has no columns, and is searched through its bitset.
+    Null,
+}
+
+impl Locator<'_> {
+    pub(crate) fn locate(&self, id: EntityId) -> Option<(usize, usize)> {
+        match self {
+            Self::Table(table) => table.locations.get(&id).copied(),
+            Self::SparseSet(sparse) => sparse.get(id).map(|&row| (0, row)),
+            Self::HashMap(map) => map.get(&id).map(|&row| (0, row)),
+            Self::Null => None,
+        }
+    }
+
+    /// Returns the index of the column of `archetype`. Storages which
+    /// aren't tables only have one column.
+    pub(crate) fn column_of(&self, archetype: Option<ArchetypeId>) -> Option<usize> {
+        match self {
+            Self::Table(table) => table.columns.get(&archetype).copied(),
+            Self::Null => None,
+            _ => Some(0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ColumnRef<'a, T> {
+    pub(crate) archetype: Option<ArchetypeId>,
+    pub(crate) ids: &'a [EntityId],
+    pub(crate) comps: &'a [T],
+}
+
+#[derive(Debug)]
+pub(crate) struct RawColumn<'a, T> {
+    pub(crate) archetype: Option<ArchetypeId>,
+    pub(crate) ids: &'a [EntityId],
+    pub(crate) comps: *mut T,
+    pub(crate) ticks: *mut ChangeTicks,
+}
+
+/// The columns of a storage, split so the components of different rows
+/// can be borrowed mutably at the same time.
+#[derive(Debug)]
+pub(crate) struct RawParts<'a, T> {
+    pub(crate) columns: Vec<RawColumn<'a, T>>,
+    pub(crate) locator: Locator<'a>,
+    pub(crate) tag_words: &'a [u64],
+    pub(crate) tag_ticks: RawSparseArray<'a, ChangeTicks>,
+    pub(crate) change_tick: u64,
+}
+
+/// The parts of a storage with its component type erased, which can find
+/// the components of entities as bytes. Like [`RawParts`], the pointers
+/// are split off the storage once, so the components of different
+/// entities can be borrowed at the same time.
+#[derive(Debug)]
+pub(crate) struct ErasedParts<'a> {
+    // The start of the components and ticks of every column.
+    pub(crate) columns: Vec<(*mut u8, *mut ChangeTicks)>,
+    // The size of a single component, padded to its alignment.
+    pub(crate) size: usize,
+    pub(crate) locator: Locator<'a>,
+    pub(crate) tags: Option<(&'a [u64], RawSparseArray<'a, ChangeTicks>)>,
+    // A dangling pointer handed out for the components in `tags`, which
+    // are zero-sized.
+    pub(crate) tag_value: *mut u8,
+}
+
+impl ErasedParts<'_> {
+    /// Returns a pointer to the component of the entity. If `write` is
+    /// the current change tick, the component is marked as changed at
+    /// that tick.
+    ///
+    /// # Safety
+    ///
+    /// If `write` is given, the ticks of the component must not be
+    /// borrowed elsewhere.
+    pub(crate) unsafe fn get(&self, id: EntityId, write: Option<u64>) -> Option<*mut u8> {
+        if let Some((words, ticks)) = &self.tags {
+            if tag::contains(words, id) {
+                if let Some(change_tick) = write {
+                    (*ticks.get(id)?).set_changed(change_tick);
+                }
+
+                return Some(self.tag_value);
+            }
+        }
+
+        let (column, row) = self.locator.locate(id)?;
+        let (comps, ticks) = self.columns[column];
+
+        if let Some(change_tick) = write {
+            (*ticks.add(row)).set_changed(change_tick);
+        }
+
+        Some(comps.add(row * self.size))
+    }
+}
+
+impl<T: Component> Default for ComponentStorage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug)]
 pub struct Read<'a, T: Component> {
     storage: AtomicRef<'a, ComponentStorage<T>>,
@@ -434,7 +1393,7 @@ impl<T: Component> Deref for Read<'_, T> {
     type Target = ComponentStorage<T>;
 
     fn deref(&self) -> &ComponentStorage<T> {
-        &*self.storage
+        &self.storage
     }
 }
 
@@ -444,7 +1403,10 @@ pub struct Write<'a, T: Component> {
 }
 
 impl<'a, T: Component> Write<'a, T> {
-    pub fn new(storage: AtomicRefMut<'a, ComponentStorage<T>>) -> Self {
+    /// Wraps the storage, recording `change_tick` for every component
+    /// added or changed through it.
+    pub fn new(mut storage: AtomicRefMut<'a, ComponentStorage<T>>, change_tick: u64) -> Self {
+        storage.set_change_tick(change_tick);
         Self { storage }
     }
 }
@@ -453,12 +1415,182 @@ impl<T: Component> Deref for Write<'_, T> {
     type Target = ComponentStorage<T>;
 
     fn deref(&self) -> &ComponentStorage<T> {
-        &*self.storage
+        &self.storage
     }
 }
 
 impl<T: Component> DerefMut for Write<'_, T> {
     fn deref_mut(&mut self) -> &mut ComponentStorage<T> {
-        &mut *self.storage
+        &mut self.storage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{drops, Counted};
+
+    use super::*;
+
+    fn allocator_with(count: u32) -> ComponentStorageAllocator {
+        let mut allocator = ComponentStorageAllocator::new();
+        assert!(allocator.register::<Counted<u32>>());
+
+        {
+            let mut storage = allocator.get_mut::<Counted<u32>>().unwrap();
+            for id in 0..count {
+                let counted = Counted(id);
+                assert!(storage.push(u64::from(id), counted).is_ok());
+            }
+        }
+
+        allocator
+    }
+
+    #[test]
+    fn register() {
+        let mut allocator = ComponentStorageAllocator::new();
+
+        assert!(allocator.register::<Counted<u32>>());
+        assert!(!allocator.register::<Counted<u32>>());
+        assert!(allocator.contains::<Counted<u32>>());
+        assert!(!allocator.contains::<u32>());
+        assert!(allocator.registry().get::<Counted<u32>>().is_some());
+    }
+
+    #[test]
+    fn get() {
+        let allocator = allocator_with(3);
+
+        let storage = allocator.get::<Counted<u32>>().unwrap();
+        assert_eq!(storage.len(), 3);
+        assert_eq!(storage.get(1).map(|counted| counted.0), Some(1));
+        assert!(allocator.get::<u32>().is_none());
+
+        // A second shared borrow succeeds while the first is alive, but a
+        // mutable one doesn't.
+        assert!(allocator.try_get::<Counted<u32>>().is_some());
+        assert!(allocator.try_get_mut::<Counted<u32>>().is_none());
+    }
+
+    #[test]
+    fn get_mut() {
+        let allocator = allocator_with(3);
+
+        {
+            let mut storage = allocator.get_mut::<Counted<u32>>().unwrap();
+            storage.get_mut(2).unwrap().0 = 20;
+
+            let replaced = storage.insert(0, Counted(10));
+            assert_eq!(replaced.map(|counted| counted.0), Some(0));
+        }
+        assert_eq!(drops(), 1);
+
+        let storage = allocator.get::<Counted<u32>>().unwrap();
+        assert_eq!(storage.get(0).map(|counted| counted.0), Some(10));
+        assert_eq!(storage.get(2).map(|counted| counted.0), Some(20));
+    }
+
+    #[test]
+    fn remove_storage() {
+        let mut allocator = allocator_with(3);
+
+        let storage = allocator.remove_storage::<Counted<u32>>().unwrap();
+        assert!(!allocator.contains::<Counted<u32>>());
+        assert!(allocator.remove_storage::<Counted<u32>>().is_none());
+        assert_eq!(drops(), 0);
+
+        assert_eq!(storage.len(), 3);
+        drop(storage);
+        assert_eq!(drops(), 3);
+    }
+
+    #[test]
+    fn remove_components() {
+        let mut allocator = allocator_with(3);
+
+        allocator.remove_components(Entity::new(1), 4);
+        assert_eq!(drops(), 1);
+
+        let storage = allocator.get::<Counted<u32>>().unwrap();
+        assert!(!storage.contains(1));
+        assert_eq!(storage.len(), 2);
+
+        // The removal is reported to systems which last ran before it.
+        assert_eq!(storage.removed_since(3).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(storage.removed_since(4).count(), 0);
+    }
+
+    #[test]
+    fn default_storage_keeps_order() {
+        let mut storage = ComponentStorage::<u32>::new();
+        assert_eq!(storage.kind(), StorageKind::Ordered);
+
+        for id in 0..5 {
+            storage.push(id, id as u32 * 10).unwrap();
+        }
+
+        assert_eq!(storage.remove_by_id(1), Some(10));
+        assert_eq!(storage.remove(0), Some((0, 0)));
+        assert_eq!(storage.pop(), Some((4, 40)));
+
+        let ids: Vec<_> = storage.iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(storage.get(3), Some(&30));
+    }
+
+    #[test]
+    fn sparse_set_swaps_on_removal() {
+        let mut storage = ComponentStorage::<u32>::with_kind(StorageKind::SparseSet);
+
+        for id in 0..4 {
+            storage.push(id, id as u32).unwrap();
+        }
+
+        assert_eq!(storage.remove_by_id(0), Some(0));
+        let ids: Vec<_> = storage.iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![3, 1, 2]);
+        assert_eq!(storage.get(3), Some(&3));
+    }
+
+    #[test]
+    fn move_entity() {
+        let mut allocator = ComponentStorageAllocator::new();
+        allocator.register_with::<u32, _>(ComponentStorage::table);
+        allocator.register_with::<u64, _>(ComponentStorage::table);
+        allocator
+            .get_mut::<u32>()
+            .unwrap()
+            .push_in(Some(0), 7, 1)
+            .unwrap();
+        allocator
+            .get_mut::<u64>()
+            .unwrap()
+            .push_in(Some(0), 7, 1)
+            .unwrap();
+
+        // Only the storages of the given components are visited.
+        let id = allocator.registry().get::<u32>().unwrap();
+        let components: ComponentSet = std::iter::once(id).collect();
+        allocator.move_entity(Entity::new(7), &components, 1);
+
+        assert_eq!(archetype_of::<u32>(&allocator, 7), Some(1));
+        assert_eq!(archetype_of::<u64>(&allocator, 7), Some(0));
+    }
+
+    fn archetype_of<T: Component>(
+        allocator: &ComponentStorageAllocator,
+        id: EntityId,
+    ) -> Option<ArchetypeId> {
+        let storage = allocator.get::<T>()?;
+        let (column, _) = storage.locate(id)?;
+        storage.columns[column].archetype
+    }
+
+    #[test]
+    fn allocator_drop() {
+        let allocator = allocator_with(5);
+
+        drop(allocator);
+        assert_eq!(drops(), 5);
     }
 }
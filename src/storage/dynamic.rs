@@ -0,0 +1,386 @@
+use std::{
+    alloc::{self, Layout},
+    ptr::{self, NonNull},
+    slice,
+};
+
+use crate::{
+    change::ChangeTicks,
+    component::{ComponentId, DropFn},
+    entity::EntityId,
+    storage::{sparse::SparseArray, ErasedParts, Locator},
+};
+
+/// The storage of a component registered at runtime with
+/// [`World::register_dynamic`](crate::World::register_dynamic), which
+/// has no Rust type. Components are kept as raw bytes in a single
+/// column, indexed like a sparse set.
+#[derive(Debug)]
+pub struct DynamicStorage {
+    component: ComponentId,
+    // The layout of a single component, padded to its alignment.
+    layout: Layout,
+    drop: Option<DropFn>,
+    data: NonNull<u8>,
+    capacity: usize,
+    ids: Vec<EntityId>,
+    ticks: Vec<ChangeTicks>,
+    index: SparseArray<usize>,
+    change_tick: u64,
+}
+
+// SAFETY: the storage owns its components, which the caller of
+// `DynamicStorage::push` guarantees can be sent and shared between
+// threads.
+unsafe impl Send for DynamicStorage {}
+unsafe impl Sync for DynamicStorage {}
+
+impl DynamicStorage {
+    pub(crate) fn new(component: ComponentId, layout: Layout, drop: Option<DropFn>) -> Self {
+        let layout = layout.pad_to_align();
+
+        // Zero-sized components never need any memory.
+        let capacity = if layout.size() == 0 { usize::MAX } else { 0 };
+
+        Self {
+            component,
+            layout,
+            drop,
+            data: dangling(layout),
+            capacity,
+            ids: Vec::new(),
+            ticks: Vec::new(),
+            index: SparseArray::new(),
+            change_tick: 0,
+        }
+    }
+
+    /// The id of the component kept in this storage.
+    pub fn component(&self) -> ComponentId {
+        self.component
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The function components of this storage are dropped with, if any.
+    pub fn drop_fn(&self) -> Option<DropFn> {
+        self.drop
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    /// Sets the tick recorded for components which are added or changed
+    /// through this storage from now on.
+    pub fn set_change_tick(&mut self, tick: u64) {
+        self.change_tick = tick;
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.locate(id).is_some()
+    }
+
+    /// The entities which have the component, in storage order.
+    pub fn ids(&self) -> &[EntityId] {
+        &self.ids
+    }
+
+    /// Copies a component out of `src` and stores it for the entity.
+    /// Returns `false` if the entity already had one, in which case
+    /// nothing is copied.
+    ///
+    /// # Safety
+    ///
+    /// `src` must point to a valid component, which can be sent and
+    /// shared between threads and dropped with the drop function of the
+    /// storage. The storage takes ownership of it, so it must not be
+    /// used or dropped afterwards unless this returns `false`.
+    pub unsafe fn push(&mut self, id: EntityId, src: *const u8) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+
+        if self.ids.len() == self.capacity {
+            self.grow();
+        }
+
+        let row = self.ids.len();
+        ptr::copy_nonoverlapping(src, self.row_ptr(row), self.layout.size());
+
+        self.index.insert(id, row);
+        self.ids.push(id);
+        self.ticks.push(ChangeTicks::new(self.change_tick));
+        true
+    }
+
+    /// Removes and drops the component of the entity. Returns `false` if
+    /// the entity had none.
+    pub fn remove(&mut self, id: EntityId) -> bool {
+        let row = match self.locate(id) {
+            Some(row) => row,
+            None => return false,
+        };
+
+        if let Some(drop) = self.drop {
+            // SAFETY: the row holds a valid component, which is forgotten
+            // by the storage below.
+            unsafe { drop(self.row_ptr(row)) };
+        }
+
+        let last = self.ids.len() - 1;
+        if row != last {
+            // SAFETY: both rows are in bounds and distinct. The last row
+            // is moved into the hole left by the dropped component.
+            unsafe {
+                ptr::copy_nonoverlapping(self.row_ptr(last), self.row_ptr(row), self.layout.size());
+            }
+        }
+
+        self.ids.swap_remove(row);
+        self.ticks.swap_remove(row);
+        self.index.remove(id);
+
+        if let Some(&moved) = self.ids.get(row) {
+            self.index.insert(moved, row);
+        }
+
+        true
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&[u8]> {
+        let row = self.locate(id)?;
+
+        // SAFETY: the row is in bounds, and holds an initialized
+        // component.
+        Some(unsafe { slice::from_raw_parts(self.row_ptr(row), self.layout.size()) })
+    }
+
+    /// Mutably borrows the bytes of the component of the entity, marking
+    /// it as changed.
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut [u8]> {
+        let ptr = self.get_ptr(id)?;
+
+        // SAFETY: the pointer is to an initialized component, which is
+        // borrowed mutably along with the storage.
+        Some(unsafe { slice::from_raw_parts_mut(ptr, self.layout.size()) })
+    }
+
+    /// Returns a pointer to the component of the entity, marking it as
+    /// changed. The pointer is invalidated when a component is added to
+    /// or removed from the storage.
+    pub fn get_ptr(&mut self, id: EntityId) -> Option<*mut u8> {
+        let row = self.locate(id)?;
+        self.ticks[row].set_changed(self.change_tick);
+
+        Some(self.row_ptr(row))
+    }
+
+    pub fn ticks_of(&self, id: EntityId) -> Option<ChangeTicks> {
+        self.locate(id).map(|row| self.ticks[row])
+    }
+
+    /// Iterates every entity in the storage along with the bytes of its
+    /// component.
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &'_ [u8])> {
+        self.ids.iter().enumerate().map(move |(row, &id)| {
+            // SAFETY: every row below the length is initialized.
+            let bytes = unsafe { slice::from_raw_parts(self.row_ptr(row), self.layout.size()) };
+            (id, bytes)
+        })
+    }
+
+    /// Splits the storage into its parts, so components of different
+    /// entities can be borrowed at the same time.
+    pub(crate) fn erased_parts(&mut self) -> ErasedParts<'_> {
+        ErasedParts {
+            columns: vec![(self.data.as_ptr(), self.ticks.as_mut_ptr())],
+            size: self.layout.size(),
+            locator: Locator::SparseSet(&self.index),
+            tags: None,
+            tag_value: ptr::null_mut(),
+        }
+    }
+
+    pub(crate) fn locate(&self, id: EntityId) -> Option<usize> {
+        self.index.get(id).copied()
+    }
+
+    pub(crate) fn row_ptr(&self, row: usize) -> *mut u8 {
+        // The offset stays within the allocation for every row up to the
+        // capacity, and is zero for zero-sized components.
+        self.data.as_ptr().wrapping_add(row * self.layout.size())
+    }
+
+    fn grow(&mut self) {
+        let capacity = if self.capacity == 0 {
+            4
+        } else {
+            self.capacity * 2
+        };
+        let new_layout = array_layout(self.layout, capacity);
+
+        // SAFETY: components are never zero-sized here, so neither layout
+        // has a size of zero, and the old layout is the one the memory was
+        // allocated with.
+        let data = unsafe {
+            if self.capacity == 0 {
+                alloc::alloc(new_layout)
+            } else {
+                let old_layout = array_layout(self.layout, self.capacity);
+                alloc::realloc(self.data.as_ptr(), old_layout, new_layout.size())
+            }
+        };
+
+        self.data = NonNull::new(data).unwrap_or_else(|| alloc::handle_alloc_error(new_layout));
+        self.capacity = capacity;
+    }
+}
+
+impl Drop for DynamicStorage {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            for row in 0..self.ids.len() {
+                // SAFETY: every row below the length holds a valid
+                // component, which is dropped exactly once here.
+                unsafe { drop(self.row_ptr(row)) };
+            }
+        }
+
+        if self.layout.size() != 0 && self.capacity != 0 {
+            // SAFETY: the memory was allocated with this layout.
+            unsafe { alloc::dealloc(self.data.as_ptr(), array_layout(self.layout, self.capacity)) };
+        }
+    }
+}
+
+fn array_layout(layout: Layout, capacity: usize) -> Layout {
+    layout
+        .size()
+        .checked_mul(capacity)
+        .and_then(|size| Layout::from_size_align(size, layout.align()).ok())
+        .expect("Dynamic component storage capacity overflowed.")
+}
+
+fn dangling(layout: Layout) -> NonNull<u8> {
+    // SAFETY: the alignment of a layout is never zero.
+    unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(layout.align())) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{self, ManuallyDrop};
+
+    use crate::test_utils::{drop_as, drops, Counted};
+
+    use super::*;
+
+    fn storage_of<T>() -> DynamicStorage {
+        DynamicStorage::new(0, Layout::new::<T>(), Some(drop_as::<T>))
+    }
+
+    fn push<T>(storage: &mut DynamicStorage, id: EntityId, value: T) -> bool {
+        let value = ManuallyDrop::new(value);
+        unsafe { storage.push(id, &*value as *const T as *const u8) }
+    }
+
+    fn get<T>(storage: &DynamicStorage, id: EntityId) -> Option<&T> {
+        let bytes = storage.get(id)?;
+        assert_eq!(bytes.len(), mem::size_of::<T>());
+        Some(unsafe { &*(bytes.as_ptr() as *const T) })
+    }
+
+    #[test]
+    fn grows_and_keeps_values() {
+        let mut storage = storage_of::<String>();
+
+        // Enough components to reallocate the column several times.
+        for id in 0..100 {
+            assert!(push(&mut storage, id, id.to_string()));
+        }
+        for id in (0..100).step_by(3) {
+            assert!(storage.remove(id));
+        }
+        assert!(!storage.remove(0));
+
+        assert_eq!(storage.len(), 66);
+        for id in 0..100 {
+            let expected = (id % 3 != 0).then(|| id.to_string());
+            assert_eq!(get::<String>(&storage, id), expected.as_ref());
+        }
+
+        let bytes = storage.get_mut(1).unwrap();
+        let value = unsafe { &mut *(bytes.as_mut_ptr() as *mut String) };
+        value.push('!');
+        assert_eq!(get::<String>(&storage, 1).map(String::as_str), Some("1!"));
+    }
+
+    #[test]
+    fn drops_every_value_once() {
+        let mut storage = storage_of::<Counted<u64>>();
+
+        for id in 0..10 {
+            assert!(push(&mut storage, id, Counted(id)));
+        }
+
+        // A rejected value is still owned by the caller.
+        let rejected = ManuallyDrop::new(Counted(0u64));
+        assert!(!unsafe { storage.push(4, &*rejected as *const Counted<u64> as *const u8) });
+        drop(ManuallyDrop::into_inner(rejected));
+        assert_eq!(drops(), 1);
+
+        for id in 0..3 {
+            storage.remove(id);
+        }
+        assert_eq!(drops(), 4);
+
+        drop(storage);
+        assert_eq!(drops(), 11);
+    }
+
+    #[test]
+    fn zero_sized() {
+        let layout = Layout::from_size_align(0, 8).unwrap();
+        let mut storage = DynamicStorage::new(0, layout, None);
+
+        for id in 0..10 {
+            assert!(unsafe { storage.push(id, ptr::NonNull::<u64>::dangling().as_ptr().cast()) });
+        }
+        storage.remove(4);
+
+        assert_eq!(storage.len(), 9);
+        assert_eq!(storage.get(3), Some(&[][..]));
+        assert_eq!(storage.get(4), None);
+        assert_eq!(storage.get_ptr(5).map(|ptr| ptr as usize % 8), Some(0));
+    }
+
+    #[test]
+    fn over_aligned() {
+        #[repr(align(32))]
+        #[derive(Debug, PartialEq)]
+        struct Aligned(u8);
+
+        let mut storage = storage_of::<Aligned>();
+        assert_eq!(storage.layout().size(), 32);
+
+        for id in 0..9 {
+            assert!(push(&mut storage, id, Aligned(id as u8)));
+        }
+
+        for id in 0..9 {
+            let ptr = storage.get_ptr(id).unwrap();
+            assert_eq!(ptr as usize % 32, 0);
+            assert_eq!(get::<Aligned>(&storage, id), Some(&Aligned(id as u8)));
+        }
+    }
+}
@@ -1,9 +1,19 @@
+pub(crate) use component::{ColumnRef, ErasedParts, Locator, RawColumn};
 pub use component::{
-    ComponentStorage, ComponentStorageAllocator, Read as ReadComponent, Write as WriteComponent,
+    ComponentStorage, ComponentStorageAllocator, Read as ReadComponent, StorageKind,
+    Write as WriteComponent,
 };
+pub use dynamic::DynamicStorage;
+pub use non_send::{NonSend, NonSendMut, NonSendStorage};
 pub use resource::{
     Read as ReadResource, ResourceStorage, ResourceStorageAllocator, Write as WriteResource,
 };
+pub(crate) use sparse::RawSparseArray;
+pub(crate) use tag::{contains as contains_tag, value_mut, value_ref};
 
 mod component;
+mod dynamic;
+mod non_send;
 mod resource;
+mod sparse;
+mod tag;
@@ -0,0 +1,266 @@
+use std::{
+    any::{self, Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
+    mem,
+    ops::{Deref, DerefMut},
+    thread::{self, ThreadId},
+};
+
+use crate::utils;
+
+/// A store for resources which are not `Send` or `Sync`. The store
+/// belongs to the thread which claims it, which is the thread owning the
+/// dispatcher, or otherwise the thread which stores the first resource
+/// in it. Every access to it from any other thread fails.
+#[derive(Debug)]
+pub struct NonSendStorage {
+    owner: Option<ThreadId>,
+    inner: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+}
+
+impl NonSendStorage {
+    pub fn new() -> Self {
+        Self {
+            owner: None,
+            inner: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the current thread owns the store, and so is
+    /// allowed to access it. Every thread owns a store which hasn't been
+    /// claimed yet.
+    pub fn is_owner(&self) -> bool {
+        self.owner
+            .is_none_or(|owner| owner == thread::current().id())
+    }
+
+    /// Makes the current thread the owner of the store. The dispatcher
+    /// claims the store of its world when it is created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the store contains resources owned by another thread.
+    pub fn claim(&mut self) {
+        assert!(
+            self.inner.is_empty() || self.is_owner(),
+            "Non-Send resources can't be moved to another thread."
+        );
+
+        self.owner = Some(thread::current().id());
+    }
+
+    /// Stores the resource, replacing and returning the resource of the
+    /// same type if one was already stored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the owning thread.
+    pub fn insert<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.assert_owner::<T>();
+        self.owner = Some(thread::current().id());
+
+        self.inner
+            .insert(TypeId::of::<T>(), RefCell::new(Box::new(resource)))
+            .map(|cell| Self::unbox(cell.into_inner()))
+    }
+
+    /// Removes the resource of type `T` and returns it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the owning thread.
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.assert_owner::<T>();
+
+        self.inner
+            .remove(&TypeId::of::<T>())
+            .map(|cell| Self::unbox(cell.into_inner()))
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.inner.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Borrows the resource of type `T`. Returns `None` if no such
+    /// resource is stored, or if it is currently borrowed mutably.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the owning thread.
+    pub fn try_get<T: 'static>(&self) -> Option<NonSend<'_, T>> {
+        self.assert_owner::<T>();
+
+        let borrow = self.inner.get(&TypeId::of::<T>())?.try_borrow().ok()?;
+        let value = Ref::map(borrow, |boxed| Self::downcast_ref(&**boxed));
+
+        Some(NonSend { value })
+    }
+
+    /// Mutably borrows the resource of type `T`. Returns `None` if no
+    /// such resource is stored, or if it is currently borrowed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the owning thread.
+    pub fn try_get_mut<T: 'static>(&self) -> Option<NonSendMut<'_, T>> {
+        self.assert_owner::<T>();
+
+        let borrow = self.inner.get(&TypeId::of::<T>())?.try_borrow_mut().ok()?;
+        let value = RefMut::map(borrow, |boxed| Self::downcast_mut(&mut **boxed));
+
+        Some(NonSendMut { value })
+    }
+
+    /// Retrieves the resource of type `T` without going through its
+    /// cell, which is possible because `self` is borrowed exclusively.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the owning thread.
+    pub fn get_exclusive<T: 'static>(&mut self) -> Option<&mut T> {
+        self.assert_owner::<T>();
+
+        self.inner
+            .get_mut(&TypeId::of::<T>())
+            .map(|cell| Self::downcast_mut(&mut **cell.get_mut()))
+    }
+
+    fn assert_owner<T: 'static>(&self) {
+        assert!(
+            self.is_owner(),
+            "Non-Send resource {} can only be accessed from the thread which owns the world.",
+            any::type_name::<T>(),
+        );
+    }
+
+    fn unbox<T: 'static>(boxed: Box<dyn Any>) -> T {
+        match boxed.downcast() {
+            Ok(resource) => *resource,
+            Err(_) => unsafe {
+                utils::debug_unreachable("Non-Send resource was stored under the wrong type.")
+            },
+        }
+    }
+
+    fn downcast_ref<T: 'static>(value: &dyn Any) -> &T {
+        value.downcast_ref().unwrap_or_else(|| unsafe {
+            utils::debug_unreachable("Non-Send resource was stored under the wrong type.")
+        })
+    }
+
+    fn downcast_mut<T: 'static>(value: &mut dyn Any) -> &mut T {
+        value.downcast_mut().unwrap_or_else(|| unsafe {
+            utils::debug_unreachable("Non-Send resource was stored under the wrong type.")
+        })
+    }
+}
+
+impl Default for NonSendStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: The resources are only ever accessed, moved out of and dropped
+// on the owning thread, which every method checks before touching them.
+unsafe impl Send for NonSendStorage {}
+
+unsafe impl Sync for NonSendStorage {}
+
+impl Drop for NonSendStorage {
+    fn drop(&mut self) {
+        // Dropping the resources on any other thread would be unsound, so
+        // they are leaked instead.
+        if !self.is_owner() {
+            mem::forget(mem::take(&mut self.inner));
+        }
+    }
+}
+
+/// A shared borrow of a non-Send resource.
+#[derive(Debug)]
+pub struct NonSend<'a, T: 'static> {
+    value: Ref<'a, T>,
+}
+
+impl<T: 'static> Deref for NonSend<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+/// An exclusive borrow of a non-Send resource.
+#[derive(Debug)]
+pub struct NonSendMut<'a, T: 'static> {
+    value: RefMut<'a, T>,
+}
+
+impl<T: 'static> Deref for NonSendMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: 'static> DerefMut for NonSendMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn owned_by_first_inserting_thread() {
+        let storage = NonSendStorage::new();
+        assert!(storage.is_owner());
+
+        let mut storage = thread::spawn(move || {
+            let mut storage = storage;
+            storage.insert(Rc::new(1u32));
+            assert!(storage.is_owner());
+            assert_eq!(**storage.try_get::<Rc<u32>>().unwrap(), 1);
+
+            storage.remove::<Rc<u32>>();
+            storage
+        })
+        .join()
+        .unwrap();
+
+        // An empty store can be claimed by another thread.
+        assert!(!storage.is_owner());
+        storage.claim();
+        assert!(storage.is_owner());
+        storage.insert(Rc::new(2u32));
+    }
+
+    #[test]
+    fn rejects_other_threads() {
+        let mut storage = NonSendStorage::new();
+        storage.insert(Rc::new(1u32));
+
+        thread::scope(|scope| {
+            let storage = &storage;
+            let borrowed = scope.spawn(move || {
+                assert!(!storage.is_owner());
+                storage.try_get::<Rc<u32>>().is_some()
+            });
+            assert!(borrowed.join().is_err());
+        });
+
+        thread::scope(|scope| {
+            let storage = &mut storage;
+            assert!(scope.spawn(move || storage.claim()).join().is_err());
+        });
+
+        assert_eq!(**storage.try_get::<Rc<u32>>().unwrap(), 1);
+    }
+}
@@ -1,36 +1,71 @@
 use std::{
-    any::{self, TypeId},
+    any::{self, Any, TypeId},
     collections::{hash_map::Entry, HashMap},
-    fmt, mem,
+    mem,
     ops::{Deref, DerefMut},
 };
 
 use crate::{
     cell::{AtomicRef, AtomicRefCell, AtomicRefMut},
+    change::{ChangeTicks, Mut, SystemTicks},
     resource::Resource,
+    storage::NonSendStorage,
     utils,
 };
 
-type ResourceDropFn = unsafe fn(ResourceStorageBytes);
+/// A `ResourceStorage<T>` with its resource type erased.
+type ErasedStorage = Box<dyn Any + Send + Sync>;
 
 #[derive(Debug)]
 pub struct ResourceStorageAllocator {
-    inner: HashMap<TypeId, AtomicRefCell<(ResourceStorageBytes, ResourceDropFn)>>,
+    inner: HashMap<TypeId, AtomicRefCell<ErasedStorage>>,
+    non_send: NonSendStorage,
+    // Resources which have been taken out by `World::resource_scope`.
+    scoped: Vec<TypeId>,
+    // The tick recorded for resources registered or changed through the
+    // allocator itself, rather than through a system.
+    change_tick: u64,
 }
 
 impl ResourceStorageAllocator {
     pub fn new() -> Self {
         Self {
             inner: HashMap::new(),
+            non_send: NonSendStorage::new(),
+            scoped: Vec::new(),
+            change_tick: 0,
         }
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             inner: HashMap::with_capacity(capacity),
+            non_send: NonSendStorage::new(),
+            scoped: Vec::new(),
+            change_tick: 0,
         }
     }
 
+    /// The store for resources which are not `Send` or `Sync`, owned by
+    /// a single thread. See [`NonSendStorage::claim`].
+    pub fn non_send(&self) -> &NonSendStorage {
+        &self.non_send
+    }
+
+    pub fn non_send_mut(&mut self) -> &mut NonSendStorage {
+        &mut self.non_send
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    /// Sets the tick recorded for resources which are registered, or
+    /// changed through [`get_exclusive`](Self::get_exclusive), from now on.
+    pub fn set_change_tick(&mut self, tick: u64) {
+        self.change_tick = tick;
+    }
+
     /// Registers a component type with the `StorageContainer`,
     /// using the default constructor. Returns a bool indicating
     /// whether the storage was registered. If this method returns
@@ -46,6 +81,22 @@ impl ResourceStorageAllocator {
     /// was registered. If this method returns `false`, it means
     /// that the storage was already registered.
     pub fn register_with<T: Resource, F>(&mut self, f: F) -> bool
+    where
+        F: FnOnce() -> ResourceStorage<T>,
+    {
+        self.assert_not_scoped::<T>();
+
+        let change_tick = self.change_tick;
+
+        self.insert_storage(|| {
+            let mut storage = f();
+            storage.reset_ticks(change_tick);
+            storage
+        })
+    }
+
+    /// Registers the storage returned by `f` without touching its ticks.
+    fn insert_storage<T: Resource, F>(&mut self, f: F) -> bool
     where
         F: FnOnce() -> ResourceStorage<T>,
     {
@@ -56,16 +107,16 @@ impl ResourceStorageAllocator {
         match self.inner.entry(type_id) {
             Occupied(_) => false,
             Vacant(v) => {
-                let storage = f();
-
-                let drop_fn = ResourceStorage::<T>::drop_resource;
-                let bytes = ResourceStorageBytes::new(storage);
-                v.insert(AtomicRefCell::new((bytes, drop_fn)));
+                let storage: ErasedStorage = Box::new(f());
+                v.insert(AtomicRefCell::new(storage));
                 true
             }
         }
     }
 
+    /// Returns whether a storage is registered for the resource type,
+    /// which isn't the case while it is taken out by
+    /// [`begin_scope`](Self::begin_scope).
     pub fn contains<T: Resource>(&self) -> bool {
         let type_id = TypeId::of::<T>();
         self.inner.contains_key(&type_id)
@@ -75,20 +126,25 @@ impl ResourceStorageAllocator {
     /// component type. Returns `None` if no storage was registered
     /// for the component.
     pub fn get<T: Resource>(&self) -> Option<AtomicRef<'_, ResourceStorage<T>>> {
+        self.assert_not_scoped::<T>();
+
         self.inner
             .get(&TypeId::of::<T>())
-            .map(|cell| AtomicRef::map(cell.borrow(), |(bytes, _)| unsafe { bytes.cast() }))
+            .map(|cell| AtomicRef::map(cell.borrow(), |storage| cast(storage)))
     }
 
     pub fn try_get<T: Resource>(&self) -> Option<AtomicRef<'_, ResourceStorage<T>>> {
+        self.assert_not_scoped::<T>();
+
         self.inner
             .get(&TypeId::of::<T>())
-            .and_then(|cell| match cell.try_borrow() {
-                Some(borrow) => Some(AtomicRef::map(borrow, |(bytes, _)| unsafe { bytes.cast() })),
-                None => None,
-            })
+            .and_then(|cell| cell.try_borrow())
+            .map(|borrow| AtomicRef::map(borrow, |storage| cast(storage)))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn get_unchecked<T: Resource>(&self) -> AtomicRef<'_, ResourceStorage<T>> {
         let cell = self.inner.get(&TypeId::of::<T>()).unwrap_or_else(|| {
             utils::debug_closure(|| {
@@ -99,9 +155,12 @@ impl ResourceStorageAllocator {
             })
         });
 
-        AtomicRef::map(cell.borrow(), |(bytes, _)| bytes.cast())
+        AtomicRef::map(cell.borrow(), |storage| cast(storage))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn try_get_unchecked<T: Resource>(
         &self,
     ) -> Option<AtomicRef<'_, ResourceStorage<T>>> {
@@ -114,32 +173,33 @@ impl ResourceStorageAllocator {
             })
         });
 
-        match cell.try_borrow() {
-            Some(borrow) => Some(AtomicRef::map(borrow, |(bytes, _)| bytes.cast())),
-            None => None,
-        }
+        cell.try_borrow()
+            .map(|borrow| AtomicRef::map(borrow, |storage| cast(storage)))
     }
 
     /// Retrieves a mutable reference to the storage associated with
     /// the component type. Returns `None` if no storage was registered
     /// for the component.
     pub fn get_mut<T: Resource>(&self) -> Option<AtomicRefMut<'_, ResourceStorage<T>>> {
-        self.inner.get(&TypeId::of::<T>()).map(|cell| {
-            AtomicRefMut::map(cell.borrow_mut(), |(bytes, _)| unsafe { bytes.cast_mut() })
-        })
+        self.assert_not_scoped::<T>();
+
+        self.inner
+            .get(&TypeId::of::<T>())
+            .map(|cell| AtomicRefMut::map(cell.borrow_mut(), |storage| cast_mut(storage)))
     }
 
     pub fn try_get_mut<T: Resource>(&self) -> Option<AtomicRefMut<'_, ResourceStorage<T>>> {
+        self.assert_not_scoped::<T>();
+
         self.inner
             .get(&TypeId::of::<T>())
-            .and_then(|cell| match cell.try_borrow_mut() {
-                Some(borrow) => Some(AtomicRefMut::map(borrow, |(bytes, _)| unsafe {
-                    bytes.cast_mut()
-                })),
-                None => None,
-            })
+            .and_then(|cell| cell.try_borrow_mut())
+            .map(|borrow| AtomicRefMut::map(borrow, |storage| cast_mut(storage)))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn get_mut_unchecked<T: Resource>(&self) -> AtomicRefMut<'_, ResourceStorage<T>> {
         let cell = self.inner.get(&TypeId::of::<T>()).unwrap_or_else(|| {
             utils::debug_closure(|| {
@@ -150,9 +210,12 @@ impl ResourceStorageAllocator {
             })
         });
 
-        AtomicRefMut::map(cell.borrow_mut(), |(bytes, _)| bytes.cast_mut())
+        AtomicRefMut::map(cell.borrow_mut(), |storage| cast_mut(storage))
     }
 
+    /// # Safety
+    ///
+    /// A storage for `T` must have been registered with the allocator.
     pub unsafe fn try_get_mut_unchecked<T: Resource>(
         &self,
     ) -> Option<AtomicRefMut<'_, ResourceStorage<T>>> {
@@ -165,10 +228,8 @@ impl ResourceStorageAllocator {
             })
         });
 
-        match cell.try_borrow_mut() {
-            Some(borrow) => Some(AtomicRefMut::map(borrow, |(bytes, _)| bytes.cast_mut())),
-            None => None,
-        }
+        cell.try_borrow_mut()
+            .map(|borrow| AtomicRefMut::map(borrow, |storage| cast_mut(storage)))
     }
 
     pub fn get_and_register<T: Resource>(
@@ -233,64 +294,156 @@ impl ResourceStorageAllocator {
     /// returns it. Returns `None` if no storage registered for the
     /// component.
     pub fn remove_storage<T: Resource>(&mut self) -> Option<ResourceStorage<T>> {
+        self.assert_not_scoped::<T>();
+
         self.inner
             .remove(&TypeId::of::<T>())
-            .map(|cell| unsafe { cell.into_inner().0.into_storage() })
+            .map(|cell| into_storage(cell.into_inner()))
     }
-}
 
-const RES_STORAGE_BYTES: usize = mem::size_of::<ResourceStorage<()>>();
+    /// Stores the resource, replacing and returning the resource of the
+    /// same type if one was already registered. A replacing resource
+    /// counts as added at the current change tick.
+    pub fn insert<T: Resource>(&mut self, resource: T) -> Option<T> {
+        match self.get_exclusive::<T>() {
+            Some(storage) => {
+                storage.reset_ticks(storage.change_tick);
+                Some(mem::replace(&mut *storage.resource, resource))
+            }
+            None => {
+                self.register(resource);
+                None
+            }
+        }
+    }
 
-#[cfg_attr(target_pointer_width = "32", repr(C, align(4)))]
-#[cfg_attr(target_pointer_width = "64", repr(C, align(8)))]
-pub struct ResourceStorageBytes {
-    bytes: [u8; RES_STORAGE_BYTES],
-}
+    /// Removes the resource of type `T` and returns it. Returns `None`
+    /// if no resource of that type was registered.
+    pub fn remove<T: Resource>(&mut self) -> Option<T> {
+        self.remove_storage::<T>().map(ResourceStorage::into_inner)
+    }
 
-impl ResourceStorageBytes {
-    pub fn new<T: Resource>(storage: ResourceStorage<T>) -> Self {
-        unsafe {
-            // SAFETY: ResourceStorage<T> and StorageBytes both
-            // have the same size and alignment, so this is just
-            // a direct conversion to the raw bytes of the storage.
-            mem::transmute(storage)
-        }
+    /// Retrieves a mutable reference to the storage associated with the
+    /// resource type without borrowing it through its cell, which is
+    /// possible because `self` is borrowed exclusively. Returns `None` if
+    /// no storage was registered for the resource.
+    pub fn get_exclusive<T: Resource>(&mut self) -> Option<&mut ResourceStorage<T>> {
+        self.assert_not_scoped::<T>();
+
+        let change_tick = self.change_tick;
+
+        self.inner.get_mut(&TypeId::of::<T>()).map(|cell| {
+            let storage = cast_mut::<T>(cell.get_mut());
+            storage.set_change_tick(change_tick);
+            storage
+        })
     }
 
-    pub unsafe fn cast<T: Resource>(&self) -> &ResourceStorage<T> {
-        mem::transmute(self)
+    /// Removes the storage associated with the resource type until it
+    /// is returned with [`end_scope`](Self::end_scope). Any other access
+    /// to the resource in the meantime panics. Returns `None` if no
+    /// storage was registered for the resource.
+    pub fn begin_scope<T: Resource>(&mut self) -> Option<ResourceStorage<T>> {
+        let mut storage = self.remove_storage::<T>()?;
+        storage.set_change_tick(self.change_tick);
+        self.scoped.push(TypeId::of::<T>());
+        Some(storage)
     }
 
-    pub unsafe fn cast_mut<T: Resource>(&mut self) -> &mut ResourceStorage<T> {
-        mem::transmute(self)
+    /// Returns a storage taken out by [`begin_scope`](Self::begin_scope).
+    pub fn end_scope<T: Resource>(&mut self, storage: ResourceStorage<T>) {
+        let type_id = TypeId::of::<T>();
+        self.scoped.retain(|&scoped| scoped != type_id);
+
+        if !self.insert_storage::<T, _>(|| storage) {
+            unsafe {
+                utils::debug_unreachable("Scoped resource was registered during its scope.");
+            }
+        }
     }
 
-    pub unsafe fn into_storage<T: Resource>(self) -> ResourceStorage<T> {
-        mem::transmute(self)
+    fn assert_not_scoped<T: Resource>(&self) {
+        if !self.scoped.is_empty() && self.scoped.contains(&TypeId::of::<T>()) {
+            panic!(
+                "Resource {} was accessed while it was taken out by World::resource_scope.",
+                any::type_name::<T>(),
+            );
+        }
     }
 }
 
-impl fmt::Debug for ResourceStorageBytes {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_list().entries(&self.bytes[..]).finish()
+impl Default for ResourceStorageAllocator {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+fn cast<T: Resource>(storage: &ErasedStorage) -> &ResourceStorage<T> {
+    storage.downcast_ref().unwrap_or_else(|| unsafe {
+        utils::debug_unreachable("Resource storage was stored under the wrong type.")
+    })
+}
+
+fn cast_mut<T: Resource>(storage: &mut ErasedStorage) -> &mut ResourceStorage<T> {
+    storage.downcast_mut().unwrap_or_else(|| unsafe {
+        utils::debug_unreachable("Resource storage was stored under the wrong type.")
+    })
+}
+
+fn into_storage<T: Resource>(storage: ErasedStorage) -> ResourceStorage<T> {
+    *storage.downcast().unwrap_or_else(|_| unsafe {
+        utils::debug_unreachable("Resource storage was stored under the wrong type.")
+    })
+}
+
 #[derive(Debug)]
-#[repr(transparent)]
 pub struct ResourceStorage<T: Resource> {
     resource: Box<T>,
+    ticks: ChangeTicks,
+    // The tick recorded when the resource is dereferenced mutably, set
+    // whenever the storage is borrowed for writing.
+    change_tick: u64,
 }
 
 impl<T: Resource> ResourceStorage<T> {
     pub fn new(resource: T) -> Self {
         Self {
             resource: Box::new(resource),
+            ticks: ChangeTicks::new(0),
+            change_tick: 0,
         }
     }
 
-    unsafe fn drop_resource(bytes: ResourceStorageBytes) {
-        drop(mem::transmute::<_, Self>(bytes));
+    pub fn into_inner(self) -> T {
+        *self.resource
+    }
+
+    /// Returns the ticks at which the resource was added and last
+    /// changed.
+    pub fn ticks(&self) -> ChangeTicks {
+        self.ticks
+    }
+
+    /// Sets the tick recorded when the resource is dereferenced mutably
+    /// from now on.
+    pub fn set_change_tick(&mut self, tick: u64) {
+        self.change_tick = tick;
+    }
+
+    /// Returns the resource, which is marked as changed once it is
+    /// dereferenced mutably.
+    pub fn as_mut(&mut self) -> Mut<'_, T> {
+        Mut::new(&mut self.resource, &mut self.ticks, self.change_tick)
+    }
+
+    /// Returns the resource without marking it as changed.
+    pub fn bypass_change_detection(&mut self) -> &mut T {
+        &mut self.resource
+    }
+
+    fn reset_ticks(&mut self, tick: u64) {
+        self.ticks = ChangeTicks::new(tick);
+        self.change_tick = tick;
     }
 }
 
@@ -304,6 +457,7 @@ impl<T: Resource> Deref for ResourceStorage<T> {
 
 impl<T: Resource> DerefMut for ResourceStorage<T> {
     fn deref_mut(&mut self) -> &mut T {
+        self.ticks.set_changed(self.change_tick);
         &mut self.resource
     }
 }
@@ -311,11 +465,27 @@ impl<T: Resource> DerefMut for ResourceStorage<T> {
 #[derive(Debug)]
 pub struct Read<'a, T: Resource> {
     storage: AtomicRef<'a, ResourceStorage<T>>,
+    last_run: u64,
 }
 
 impl<'a, T: Resource> Read<'a, T> {
-    pub fn new(storage: AtomicRef<'a, ResourceStorage<T>>) -> Self {
-        Self { storage }
+    /// Wraps the storage. `last_run` is the tick the fetching system last
+    /// ran at, which [`is_added`](Self::is_added) and
+    /// [`is_changed`](Self::is_changed) compare against.
+    pub fn new(storage: AtomicRef<'a, ResourceStorage<T>>, last_run: u64) -> Self {
+        Self { storage, last_run }
+    }
+
+    /// Returns whether the resource was added since the fetching system
+    /// last ran.
+    pub fn is_added(&self) -> bool {
+        self.storage.ticks().is_added_since(self.last_run)
+    }
+
+    /// Returns whether the resource was added or changed since the
+    /// fetching system last ran.
+    pub fn is_changed(&self) -> bool {
+        self.storage.ticks().is_changed_since(self.last_run)
     }
 }
 
@@ -323,18 +493,38 @@ impl<T: Resource> Deref for Read<'_, T> {
     type Target = ResourceStorage<T>;
 
     fn deref(&self) -> &ResourceStorage<T> {
-        &*self.storage
+        &self.storage
     }
 }
 
 #[derive(Debug)]
 pub struct Write<'a, T: Resource> {
     storage: AtomicRefMut<'a, ResourceStorage<T>>,
+    last_run: u64,
 }
 
 impl<'a, T: Resource> Write<'a, T> {
-    pub fn new(storage: AtomicRefMut<'a, ResourceStorage<T>>) -> Self {
-        Self { storage }
+    /// Wraps the storage, marking the resource as changed at
+    /// `ticks.this_run()` when it is dereferenced mutably.
+    pub fn new(mut storage: AtomicRefMut<'a, ResourceStorage<T>>, ticks: SystemTicks) -> Self {
+        storage.set_change_tick(ticks.this_run());
+
+        Self {
+            storage,
+            last_run: ticks.last_run(),
+        }
+    }
+
+    /// Returns whether the resource was added since the fetching system
+    /// last ran.
+    pub fn is_added(&self) -> bool {
+        self.storage.ticks().is_added_since(self.last_run)
+    }
+
+    /// Returns whether the resource was added or changed since the
+    /// fetching system last ran.
+    pub fn is_changed(&self) -> bool {
+        self.storage.ticks().is_changed_since(self.last_run)
     }
 }
 
@@ -342,12 +532,87 @@ impl<T: Resource> Deref for Write<'_, T> {
     type Target = ResourceStorage<T>;
 
     fn deref(&self) -> &ResourceStorage<T> {
-        &*self.storage
+        &self.storage
     }
 }
 
 impl<T: Resource> DerefMut for Write<'_, T> {
     fn deref_mut(&mut self) -> &mut ResourceStorage<T> {
-        &mut *self.storage
+        &mut self.storage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{drops, Counted};
+
+    use super::*;
+
+    #[test]
+    fn register() {
+        let mut allocator = ResourceStorageAllocator::new();
+
+        assert!(allocator.register(Counted(0u32)));
+        assert!(!allocator.register(Counted(1u32)));
+        assert!(allocator.contains::<Counted<u32>>());
+        assert!(!allocator.contains::<u32>());
+
+        // The rejected resource is dropped without replacing the first.
+        assert_eq!(drops(), 1);
+        assert_eq!(allocator.get::<Counted<u32>>().unwrap().0, 0);
+    }
+
+    #[test]
+    fn get() {
+        let mut allocator = ResourceStorageAllocator::new();
+        allocator.register(Counted(3u32));
+
+        let storage = allocator.get::<Counted<u32>>().unwrap();
+        assert_eq!(storage.0, 3);
+        assert!(allocator.get::<u32>().is_none());
+
+        assert!(allocator.try_get::<Counted<u32>>().is_some());
+        assert!(allocator.try_get_mut::<Counted<u32>>().is_none());
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut allocator = ResourceStorageAllocator::new();
+        allocator.register(Counted(3u32));
+
+        allocator.get_mut::<Counted<u32>>().unwrap().0 = 4;
+        allocator.get_exclusive::<Counted<u32>>().unwrap().0 += 1;
+        assert_eq!(allocator.get::<Counted<u32>>().unwrap().0, 5);
+
+        let replaced = allocator.insert(Counted(6u32));
+        assert_eq!(replaced.as_ref().map(|counted| counted.0), Some(5));
+        drop(replaced);
+        assert_eq!(drops(), 1);
+    }
+
+    #[test]
+    fn remove_storage() {
+        let mut allocator = ResourceStorageAllocator::new();
+        allocator.register(Counted(3u32));
+
+        let storage = allocator.remove_storage::<Counted<u32>>().unwrap();
+        assert!(!allocator.contains::<Counted<u32>>());
+        assert!(allocator.remove_storage::<Counted<u32>>().is_none());
+        assert_eq!(drops(), 0);
+
+        let resource = storage.into_inner();
+        assert_eq!(resource.0, 3);
+        drop(resource);
+        assert_eq!(drops(), 1);
+    }
+
+    #[test]
+    fn allocator_drop() {
+        let mut allocator = ResourceStorageAllocator::new();
+        allocator.register(Counted(0u32));
+        allocator.register(Counted(String::from("a")));
+
+        drop(allocator);
+        assert_eq!(drops(), 2);
     }
 }
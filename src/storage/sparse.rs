@@ -0,0 +1,206 @@
+use std::{convert::TryFrom, iter, marker::PhantomData, ptr};
+
+use crate::entity::EntityId;
+
+/// The number of entities covered by a single page.
+const PAGE_LEN: usize = 256;
+
+/// A map from entity ids to values, indexed directly by id like a sparse
+/// set. The index is split into pages which are only allocated while they
+/// hold a value, so entity ids which are never reused don't make it grow
+/// by more than a pointer for every page of ids.
+#[derive(Debug)]
+pub(crate) struct SparseArray<V> {
+    pages: Vec<Option<Page<V>>>,
+}
+
+#[derive(Debug)]
+struct Page<V> {
+    values: Box<[Option<V>]>,
+    len: usize,
+}
+
+impl<V> Page<V> {
+    fn new() -> Self {
+        Self {
+            values: iter::repeat_with(|| None).take(PAGE_LEN).collect(),
+            len: 0,
+        }
+    }
+}
+
+impl<V> SparseArray<V> {
+    pub(crate) fn new() -> Self {
+        Self { pages: Vec::new() }
+    }
+
+    pub(crate) fn get(&self, id: EntityId) -> Option<&V> {
+        let (page, offset) = split(id);
+
+        self.pages.get(page)?.as_ref()?.values[offset].as_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self, id: EntityId) -> Option<&mut V> {
+        let (page, offset) = split(id);
+
+        self.pages.get_mut(page)?.as_mut()?.values[offset].as_mut()
+    }
+
+    /// Iterates every entity with a value, in ascending order of id.
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &'_ mut V)> {
+        self.pages
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(page, values)| Some((page, values.as_mut()?)))
+            .flat_map(|(page, values)| {
+                values
+                    .values
+                    .iter_mut()
+                    .enumerate()
+                    .filter_map(move |(offset, value)| {
+                        let id = (page * PAGE_LEN + offset) as EntityId;
+                        Some((id, value.as_mut()?))
+                    })
+            })
+    }
+
+    /// Splits the array into pointers to its pages, so the values of
+    /// different entities can be borrowed mutably at the same time.
+    pub(crate) fn raw_parts(&mut self) -> RawSparseArray<'_, V> {
+        let pages = self
+            .pages
+            .iter_mut()
+            .map(|page| match page {
+                Some(page) => page.values.as_mut_ptr(),
+                None => ptr::null_mut(),
+            })
+            .collect();
+
+        RawSparseArray {
+            pages,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Stores the value of the entity, returning the value it replaced.
+    pub(crate) fn insert(&mut self, id: EntityId, value: V) -> Option<V> {
+        let (page, offset) = split(id);
+
+        if page >= self.pages.len() {
+            self.pages.resize_with(page + 1, || None);
+        }
+
+        let page = self.pages[page].get_or_insert_with(Page::new);
+        let old = page.values[offset].replace(value);
+
+        if old.is_none() {
+            page.len += 1;
+        }
+
+        old
+    }
+
+    /// Removes the value of the entity, freeing its page once the page
+    /// is empty.
+    pub(crate) fn remove(&mut self, id: EntityId) -> Option<V> {
+        let (index, offset) = split(id);
+
+        let page = self.pages.get_mut(index)?.as_mut()?;
+        let old = page.values[offset].take()?;
+        page.len -= 1;
+
+        if page.len == 0 {
+            self.pages[index] = None;
+
+            while let Some(None) = self.pages.last() {
+                self.pages.pop();
+            }
+        }
+
+        Some(old)
+    }
+}
+
+impl<V> Default for SparseArray<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`SparseArray`] split into pointers to its pages, borrowed mutably
+/// for `'a`.
+#[derive(Debug)]
+pub(crate) struct RawSparseArray<'a, V> {
+    // Null for pages which aren't allocated.
+    pages: Vec<*mut Option<V>>,
+    _marker: PhantomData<&'a mut V>,
+}
+
+impl<'a, V> RawSparseArray<'a, V> {
+    /// Returns a pointer to the value of the entity.
+    pub(crate) fn get(&self, id: EntityId) -> Option<*mut V> {
+        let (page, offset) = split(id);
+        let page = *self.pages.get(page)?;
+
+        if page.is_null() {
+            return None;
+        }
+
+        // SAFETY: the page is allocated and `PAGE_LEN` values long, and
+        // the array is borrowed mutably, so it is neither freed nor
+        // borrowed elsewhere. Only the slot of this entity is borrowed.
+        unsafe { (*page.add(offset)).as_mut().map(|value| value as *mut V) }
+    }
+}
+
+/// Splits an entity id into the index of its page and its offset in it.
+fn split(id: EntityId) -> (usize, usize) {
+    let page = usize::try_from(id / PAGE_LEN as EntityId).unwrap_or_else(|_| {
+        panic!("Entity {} does not fit in the index of a sparse set.", id);
+    });
+
+    (page, (id % PAGE_LEN as EntityId) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut array = SparseArray::new();
+
+        assert_eq!(array.insert(3, 'a'), None);
+        assert_eq!(array.insert(1000, 'b'), None);
+        assert_eq!(array.insert(3, 'c'), Some('a'));
+        assert_eq!(array.get(3), Some(&'c'));
+        assert_eq!(array.get(1000), Some(&'b'));
+        assert_eq!(array.get(4), None);
+        assert_eq!(array.get(u64::MAX), None);
+
+        assert_eq!(array.remove(1000), Some('b'));
+        assert_eq!(array.remove(1000), None);
+        assert_eq!(array.get(1000), None);
+        assert_eq!(array.get(3), Some(&'c'));
+    }
+
+    #[test]
+    fn frees_empty_pages() {
+        let mut array = SparseArray::new();
+
+        for id in 0..PAGE_LEN as EntityId * 4 {
+            array.insert(id, id);
+        }
+        assert_eq!(array.pages.len(), 4);
+
+        // Entity ids are never reused, so once the entities of a page are
+        // gone, the page is too.
+        for id in 0..PAGE_LEN as EntityId * 4 {
+            array.remove(id);
+        }
+        assert!(array.pages.is_empty());
+
+        array.insert(PAGE_LEN as EntityId * 2, 0);
+        assert_eq!(array.pages.iter().filter(|page| page.is_some()).count(), 1);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{marker::PhantomData, mem, ptr::NonNull};
+
+use crate::{
+    change::ChangeTicks,
+    entity::EntityId,
+    storage::sparse::{RawSparseArray, SparseArray},
+};
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// The entities holding a zero-sized component, as a bitset of entity
+/// indices. The components themselves take no space, so they are
+/// forgotten when inserted and conjured back up when removed.
+#[derive(Debug)]
+pub(crate) struct TagSet<T> {
+    // Trailing words without any bits set are trimmed.
+    bits: Vec<u64>,
+    ticks: SparseArray<ChangeTicks>,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> TagSet<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            bits: Vec::new(),
+            ticks: SparseArray::new(),
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.bits
+    }
+
+    pub(crate) fn contains(&self, id: EntityId) -> bool {
+        contains(&self.bits, id)
+    }
+
+    /// Adds the entity to the set. The entity must not be in the set
+    /// already.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not zero-sized.
+    pub(crate) fn insert(&mut self, id: EntityId, value: T, ticks: ChangeTicks) {
+        assert_eq!(
+            mem::size_of::<T>(),
+            0,
+            "Only zero-sized components can be stored in a tag set."
+        );
+        debug_assert!(!self.contains(id), "Entity was already in the tag set.");
+
+        let index = index(id);
+        let (word, bit) = (index / WORD_BITS, index % WORD_BITS);
+
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+
+        self.bits[word] |= 1 << bit;
+        self.ticks.insert(id, ticks);
+        self.len += 1;
+
+        // The value is zero-sized, so it is fully described by the bit.
+        mem::forget(value);
+    }
+
+    pub(crate) fn remove(&mut self, id: EntityId) -> Option<(T, ChangeTicks)> {
+        if !self.contains(id) {
+            return None;
+        }
+
+        let index = index(id);
+        self.bits[index / WORD_BITS] &= !(1 << (index % WORD_BITS));
+        self.len -= 1;
+
+        while self.bits.last() == Some(&0) {
+            self.bits.pop();
+        }
+
+        let ticks = self.ticks.remove(id)?;
+
+        // SAFETY: a value was forgotten when the entity was inserted, and
+        // is handed back exactly once here.
+        Some((unsafe { conjure() }, ticks))
+    }
+
+    pub(crate) fn ticks(&self, id: EntityId) -> Option<ChangeTicks> {
+        self.ticks.get(id).copied()
+    }
+
+    pub(crate) fn ticks_mut(&mut self, id: EntityId) -> Option<&mut ChangeTicks> {
+        self.ticks.get_mut(id)
+    }
+
+    /// The entities in the set, in ascending order.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.bits.iter().enumerate().flat_map(|(word, &bits)| {
+            let mut bits = bits;
+
+            std::iter::from_fn(move || {
+                if bits == 0 {
+                    return None;
+                }
+
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                Some((word * WORD_BITS + bit) as EntityId)
+            })
+        })
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (EntityId, ChangeTicks)> + '_ {
+        self.ids().filter_map(move |id| Some((id, self.ticks(id)?)))
+    }
+
+    /// Iterates the entities in the set along with their ticks, in
+    /// ascending order.
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &'_ mut ChangeTicks)> {
+        self.ticks.iter_mut()
+    }
+
+    /// Splits the set so the ticks of different entities can be borrowed
+    /// mutably at the same time.
+    pub(crate) fn raw_parts(&mut self) -> (&[u64], RawSparseArray<'_, ChangeTicks>) {
+        (&self.bits, self.ticks.raw_parts())
+    }
+}
+
+impl<T> Drop for TagSet<T> {
+    fn drop(&mut self) {
+        for _ in 0..self.len {
+            // SAFETY: one value was forgotten for every entity in the set.
+            drop(unsafe { conjure::<T>() });
+        }
+    }
+}
+
+/// Returns whether the bit of the entity is set in `bits`.
+pub(crate) fn contains(bits: &[u64], id: EntityId) -> bool {
+    let index = index(id);
+
+    bits.get(index / WORD_BITS)
+        .is_some_and(|word| word & (1 << (index % WORD_BITS)) != 0)
+}
+
+/// Borrows a zero-sized value, which needs no backing memory.
+pub(crate) fn value_ref<'a, T>() -> &'a T {
+    debug_assert_eq!(mem::size_of::<T>(), 0);
+
+    // SAFETY: a dangling, aligned pointer is valid for zero-sized reads.
+    unsafe { &*NonNull::dangling().as_ptr() }
+}
+
+/// Mutably borrows a zero-sized value, which needs no backing memory.
+pub(crate) fn value_mut<'a, T>() -> &'a mut T {
+    debug_assert_eq!(mem::size_of::<T>(), 0);
+
+    // SAFETY: a dangling, aligned pointer is valid for zero-sized writes,
+    // and zero-sized borrows never overlap.
+    unsafe { &mut *NonNull::dangling().as_ptr() }
+}
+
+/// # Safety
+///
+/// `T` must be zero-sized, and a value of `T` must have been forgotten
+/// for every value conjured.
+unsafe fn conjure<T>() -> T {
+    NonNull::<T>::dangling().as_ptr().read()
+}
+
+fn index(id: EntityId) -> usize {
+    super::component::sparse_index(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{drops, Counted};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Marker;
+
+    #[derive(Debug, PartialEq)]
+    #[repr(align(16))]
+    struct Aligned;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut tags = TagSet::new();
+
+        tags.insert(3, Marker, ChangeTicks::new(1));
+        tags.insert(70, Marker, ChangeTicks::new(2));
+        assert!(tags.contains(3));
+        assert!(!tags.contains(4));
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags.ids().collect::<Vec<_>>(), vec![3, 70]);
+        assert_eq!(tags.ticks(70).map(|ticks| ticks.added()), Some(2));
+
+        tags.ticks_mut(3).unwrap().set_changed(5);
+        assert_eq!(tags.remove(3).map(|(_, ticks)| ticks.changed()), Some(5));
+        assert_eq!(tags.remove(3), None);
+        assert_eq!(tags.len(), 1);
+
+        // Removing the highest entity trims the bitset.
+        assert!(tags.remove(70).is_some());
+        assert!(tags.words().is_empty());
+    }
+
+    #[test]
+    fn ticks_of_removed_entities_are_freed() {
+        let mut tags = TagSet::new();
+
+        tags.insert(5, Marker, ChangeTicks::new(0));
+        tags.insert(100_000, Marker, ChangeTicks::new(0));
+        tags.remove(100_000);
+
+        assert_eq!(tags.ticks(100_000), None);
+        assert_eq!(tags.words().len(), 1);
+        assert_eq!(
+            tags.iter_mut().map(|(id, _)| id).collect::<Vec<_>>(),
+            vec![5]
+        );
+    }
+
+    #[test]
+    fn conjured_values_are_dropped_once() {
+        let mut tags = TagSet::new();
+        for id in 0..3 {
+            tags.insert(id, Counted(()), ChangeTicks::new(0));
+        }
+        assert_eq!(drops(), 0);
+
+        drop(tags.remove(1));
+        assert_eq!(drops(), 1);
+
+        drop(tags);
+        assert_eq!(drops(), 3);
+    }
+
+    #[test]
+    fn values_need_no_memory() {
+        let a: &mut Aligned = value_mut();
+        let b: &mut Aligned = value_mut();
+        *a = Aligned;
+        *b = Aligned;
+        assert_eq!(a, b);
+        assert_eq!(a as *mut Aligned as usize % 16, 0);
+
+        let marker: &Marker = value_ref();
+        assert_eq!(*marker, Marker);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only zero-sized components")]
+    fn insert_sized_value() {
+        let mut tags = TagSet::new();
+        tags.insert(0, 1u32, ChangeTicks::new(0));
+    }
+}
@@ -1,5 +1,8 @@
+use std::any::TypeId;
+use std::fmt;
+use std::mem::ManuallyDrop;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
@@ -8,16 +11,30 @@ use crossbeam::queue::{ArrayQueue, PushError};
 use crossbeam::utils::Backoff;
 
 use crate::cell::{AtomicRefCell, AtomicRefMut};
+use crate::change::RemovedUpdateSystem;
+use crate::event::{EventUpdateSystem, Events};
+use crate::resource::Resource;
 use crate::system::executor::SystemExecutor;
-use crate::system::System;
+use crate::system::fixed::FixedTimestep;
+use crate::system::time::{Clock, Time, TimeSystem};
+use crate::system::{AccessError, RetrievalError, System};
 use crate::utils;
-use crate::world::World;
+use crate::world::{FromWorld, World};
+
+/// Called with the name of a system and the error it failed to run
+/// with. See [`DispatchBuilder::with_error_handler`].
+pub type ErrorHandler = fn(&'static str, RetrievalError);
 
 #[derive(Debug)]
 pub struct DispatchBuilder {
     thread_count: Option<usize>,
     sleep_time: Option<Duration>,
+    clock: Clock,
+    error_handler: ErrorHandler,
     systems: Vec<SystemExecutor>,
+    tick_systems: Vec<SystemExecutor>,
+    resources: Vec<fn(&mut World)>,
+    events: Vec<TypeId>,
 }
 
 impl DispatchBuilder {
@@ -25,7 +42,12 @@ impl DispatchBuilder {
         Self {
             thread_count: None,
             sleep_time: None,
+            clock: Clock::Real,
+            error_handler: log_error,
             systems: Vec::new(),
+            tick_systems: Vec::new(),
+            resources: Vec::new(),
+            events: Vec::new(),
         }
     }
 
@@ -33,7 +55,12 @@ impl DispatchBuilder {
         Self {
             thread_count: None,
             sleep_time: None,
+            clock: Clock::Real,
+            error_handler: log_error,
             systems: Vec::with_capacity(capacity),
+            tick_systems: Vec::new(),
+            resources: Vec::new(),
+            events: Vec::new(),
         }
     }
 
@@ -46,6 +73,64 @@ impl DispatchBuilder {
         self
     }
 
+    /// Registers a group of systems which run at a fixed rate. The
+    /// [`FixedTime`](crate::system::fixed::FixedTime) resource is added to the world when the dispatcher
+    /// is built.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a fixed-timestep group was already registered, since
+    /// every group would update the same `FixedTime`.
+    pub fn with_fixed_timestep(mut self, group: FixedTimestep) -> Self {
+        let existing: usize = self
+            .systems
+            .iter()
+            .map(SystemExecutor::fixed_timesteps)
+            .sum();
+        assert_single_fixed_timestep(existing + 1);
+
+        let executor = SystemExecutor::from_fixed_timestep(group);
+        self.systems.push(executor);
+        self
+    }
+
+    /// Registers an [`Events<T>`] channel. The channel is added to the
+    /// world when the dispatcher is built, and its buffers are swapped at
+    /// the start of every tick, so each event can be read during the
+    /// tick it was sent in and the tick after it. Registering the same
+    /// channel again does nothing.
+    pub fn with_event<T: Resource>(mut self) -> Self {
+        let id = TypeId::of::<T>();
+
+        if self.events.contains(&id) {
+            return self;
+        }
+
+        self.events.push(id);
+        let executor = SystemExecutor::new(EventUpdateSystem::<T>::new());
+        self.tick_systems.push(executor);
+        self.resources.push(|world| {
+            world.resource_storage_mut().register(Events::<T>::new());
+        });
+        self
+    }
+
+    /// Registers a resource to be constructed with [`FromWorld`] when the
+    /// dispatcher is built, if the world doesn't already contain one.
+    /// Systems which request the resource can then fetch it from the
+    /// first tick, rather than failing with
+    /// [`NoSuchResourceStorage`](crate::system::RetrievalError::NoSuchResourceStorage)
+    /// until it is added.
+    ///
+    /// Resources which systems fetch through [`Init`](crate::Init) are
+    /// constructed the same way without being registered here.
+    pub fn init_resource<T: Resource + FromWorld>(mut self) -> Self {
+        self.resources.push(|world| {
+            world.init_resource::<T>();
+        });
+        self
+    }
+
     pub fn with_threads(mut self, thread_count: usize) -> Self {
         self.thread_count = Some(thread_count);
         self
@@ -56,25 +141,91 @@ impl DispatchBuilder {
         self
     }
 
-    pub fn build(mut self, world: World) -> Dispatcher {
-        let dispatcher = Dispatcher::new_priv(
+    /// Sets the clock used to update the [`Time`] resource. Defaults to
+    /// [`Clock::Real`].
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Sets the function called whenever a system fails to fetch its
+    /// data, and so doesn't run. Defaults to printing the error to
+    /// standard error.
+    pub fn with_error_handler(mut self, handler: ErrorHandler) -> Self {
+        self.error_handler = handler;
+        self
+    }
+
+    /// Builds the `Dispatcher`, returning an error if any registered
+    /// system requests conflicting borrows of the same resource or
+    /// component type.
+    ///
+    /// Systems which borrow non-Send resources are kept out of the
+    /// shared queue, and are only run by [`Dispatcher::run_local`] and
+    /// [`Dispatcher::run_once`] on the thread which owns the world.
+    /// The calling thread becomes the owner of the world.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the world contains non-Send resources owned by another
+    /// thread.
+    pub fn build(mut self, mut world: World) -> Result<Dispatcher, AccessError> {
+        for executor in self.systems.iter() {
+            executor.validate()?;
+        }
+
+        for register in self.resources.iter() {
+            register(&mut world);
+        }
+
+        for executor in self.systems.iter() {
+            executor.access().resources.init_missing(&mut world);
+        }
+
+        let fixed_timesteps = self
+            .systems
+            .iter()
+            .map(SystemExecutor::fixed_timesteps)
+            .sum();
+
+        let (local, systems): (Vec<_>, Vec<_>) = self
+            .systems
+            .drain(..)
+            .partition(SystemExecutor::is_non_send);
+
+        let mut dispatcher = Dispatcher::new_priv(
             world,
-            self.systems.len(),
+            systems.len(),
             self.thread_count,
             self.sleep_time,
+            self.clock,
+            self.error_handler,
+            self.tick_systems,
         );
 
-        self.systems.drain(..).for_each(|executor| {
+        dispatcher.local = local;
+        dispatcher
+            .fixed_timesteps
+            .store(fixed_timesteps, Ordering::Release);
+
+        systems.into_iter().for_each(|executor| {
             dispatcher
                 .shared
                 .queue
                 .push_no_cache(executor)
                 .unwrap_or_else(|_| unsafe {
                     utils::debug_unreachable("Incorrect Dispatcher capacity.")
-                })
+                });
+            dispatcher.shared.systems.fetch_add(1, Ordering::AcqRel);
         });
 
-        dispatcher
+        Ok(dispatcher)
+    }
+}
+
+impl Default for DispatchBuilder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -82,19 +233,47 @@ impl DispatchBuilder {
 pub struct Dispatcher {
     threads: Vec<DispatchThread>,
     shared: Arc<ThreadShared>,
+    local: Vec<SystemExecutor>,
+    // The number of fixed-timestep groups among the executors, which is
+    // at most one.
+    fixed_timesteps: AtomicUsize,
 }
 
 impl Dispatcher {
     pub fn new(world: World, capacity: usize) -> Self {
-        Self::new_priv(world, capacity, None, None)
+        Self::new_priv(
+            world,
+            capacity,
+            None,
+            None,
+            Clock::Real,
+            log_error,
+            Vec::new(),
+        )
     }
 
     pub fn with_threads(world: World, capacity: usize, thread_count: usize) -> Self {
-        Self::new_priv(world, capacity, Some(thread_count), None)
+        Self::new_priv(
+            world,
+            capacity,
+            Some(thread_count),
+            None,
+            Clock::Real,
+            log_error,
+            Vec::new(),
+        )
     }
 
     pub fn with_sleep(world: World, capacity: usize, sleep_time: Duration) -> Self {
-        Self::new_priv(world, capacity, None, Some(sleep_time))
+        Self::new_priv(
+            world,
+            capacity,
+            None,
+            Some(sleep_time),
+            Clock::Real,
+            log_error,
+            Vec::new(),
+        )
     }
 
     pub fn with_threads_and_sleep(
@@ -103,31 +282,147 @@ impl Dispatcher {
         thread_count: usize,
         sleep_time: Duration,
     ) -> Self {
-        Self::new_priv(world, capacity, Some(thread_count), Some(sleep_time))
+        Self::new_priv(
+            world,
+            capacity,
+            Some(thread_count),
+            Some(sleep_time),
+            Clock::Real,
+            log_error,
+            Vec::new(),
+        )
+    }
+
+    /// Adds an executor to the shared queue. Executors which borrow
+    /// non-Send resources should be added with
+    /// [`add_local_executor`](Self::add_local_executor) instead, since
+    /// they would fail to fetch their resources on a dispatch thread.
+    ///
+    /// Missing resources the executor fetches through [`Init`](crate::Init)
+    /// are constructed first. The executor first runs in the tick after
+    /// the current one, and the dispatch threads are parked while it is
+    /// added.
+    ///
+    /// Returns an error if the executor requests conflicting borrows, or
+    /// the queue is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the executor contains a fixed-timestep group, and the
+    /// dispatcher already has one.
+    pub fn add_executor(&self, mut executor: SystemExecutor) -> Result<(), AddExecutorError> {
+        executor.validate()?;
+        let fixed_timesteps = self.check_fixed_timesteps(&executor);
+        self.init_missing(&executor);
+
+        // No tick can start while the threads are parked, so the executor
+        // is counted from the next tick on.
+        self.park_all();
+        executor.set_last_tick(self.shared.ticks.load(Ordering::Acquire));
+        let result = self.shared.queue.push(executor);
+        if result.is_ok() {
+            self.shared.systems.fetch_add(1, Ordering::AcqRel);
+            self.fixed_timesteps
+                .fetch_add(fixed_timesteps, Ordering::AcqRel);
+        }
+        self.resume_all();
+
+        result.map_err(AddExecutorError::QueueFull)
     }
 
-    pub fn add_executor(&self, executor: SystemExecutor) -> Result<(), SystemExecutor> {
-        self.shared.queue.push(executor)
+    /// Adds an executor which is only run by [`run_local`](Self::run_local)
+    /// and [`run_once`](Self::run_once). Returns an error if the executor
+    /// requests conflicting borrows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the executor contains a fixed-timestep group, and the
+    /// dispatcher already has one.
+    pub fn add_local_executor(&mut self, executor: SystemExecutor) -> Result<(), AccessError> {
+        executor.validate()?;
+        let fixed_timesteps = self.check_fixed_timesteps(&executor);
+        self.init_missing(&executor);
+        self.local.push(executor);
+        *self.fixed_timesteps.get_mut() += fixed_timesteps;
+        Ok(())
     }
 
-    pub fn world(&self) -> WorldHandle {
+    pub fn world(&self) -> WorldHandle<'_> {
         self.park_all();
 
-        let backoff = Backoff::new();
-        while self.shared.parked.load(Ordering::Acquire) != self.threads.len() {
-            if backoff.is_completed() {
-                thread::sleep(Duration::from_millis(1));
-            } else {
-                backoff.snooze();
+        WorldHandle(self, ManuallyDrop::new(self.shared.world.borrow_mut()))
+    }
+
+    /// Starts a new tick and runs every system in the queue exactly once
+    /// on the calling thread, in queue order, after the tick systems which
+    /// update [`Time`]. Dispatch threads are parked for the duration of
+    /// the call, and start the next tick once they resume.
+    ///
+    /// Commands pushed by component hooks are applied afterwards.
+    ///
+    /// Combined with [`Clock::Manual`], this allows the dispatcher to be
+    /// stepped deterministically.
+    pub fn run_once(&mut self) {
+        self.park_all();
+
+        {
+            let world = self.shared.world.borrow();
+            let tick = self
+                .shared
+                .start_tick(&mut self.shared.tick.borrow_mut(), &world);
+
+            let mut executors = Vec::with_capacity(self.shared.queue.len());
+            while let Some(executor) = self.shared.queue.pop() {
+                executors.push(executor);
+            }
+
+            for mut executor in executors {
+                self.shared.run(&mut executor, &world);
+                executor.set_last_tick(tick);
+                self.shared
+                    .queue
+                    .push_no_cache(executor)
+                    .expect("System queue was full.");
+            }
+
+            // Every system ran during this tick.
+            self.shared.remaining.store(0, Ordering::Release);
+
+            for executor in self.local.iter_mut() {
+                self.shared.run(executor, &world);
             }
         }
 
-        WorldHandle(self, self.shared.world.borrow_mut())
+        self.shared.world.borrow_mut().apply_commands();
+        self.resume_all();
+    }
+
+    /// Runs every system which borrows non-Send resources once on the
+    /// calling thread. Unlike [`run_once`](Self::run_once), the dispatch
+    /// threads keep running in the meantime, so this is meant to be
+    /// called repeatedly from the main loop of the thread which owns the
+    /// world.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread other than the one which owns
+    /// the world.
+    pub fn run_local(&mut self) {
+        let world = self.shared.world.borrow();
+
+        assert!(
+            world.resource_storage().read().non_send().is_owner(),
+            "Local systems can only be run on the thread which owns the world."
+        );
+
+        for executor in self.local.iter_mut() {
+            self.shared.run(executor, &world);
+        }
     }
 
     pub fn shutdown(mut self) -> World {
+        self.shared.status.store(SHUTDOWN, Ordering::Release);
         self.unpark_all();
-        self.shared.status.store(SHUTDOWN, Ordering::Relaxed);
 
         self.threads.drain(..).for_each(|thread| thread.join());
 
@@ -148,30 +443,88 @@ impl Dispatcher {
     }
 
     fn new_priv(
-        world: World,
+        mut world: World,
         capacity: usize,
         thread_count: Option<usize>,
         sleep_time: Option<Duration>,
+        clock: Clock,
+        error_handler: ErrorHandler,
+        tick_systems: Vec<SystemExecutor>,
     ) -> Self {
         let count = match thread_count {
             Some(n) => n,
             None => num_cpus::get(),
         };
 
+        // Local systems run on the thread owning the dispatcher, so it has
+        // to own the non-Send resources they fetch.
+        world.resource_storage_mut().non_send_mut().claim();
+        world.resource_storage_mut().register(Time::new());
+
+        // The tick systems are kept out of the queue, and run whenever
+        // a tick starts.
+        let mut tick = vec![
+            SystemExecutor::new(TimeSystem::new(clock)),
+            SystemExecutor::new(RemovedUpdateSystem),
+        ];
+        tick.extend(tick_systems);
+        let tick = SystemExecutor::sequence(tick);
+
+        // An `ArrayQueue` can't have a capacity of zero.
+        let queue = SystemQueue::new(capacity.max(1));
+
         let shared = Arc::new(ThreadShared::new(
-            SystemQueue::new(capacity),
+            queue,
+            tick,
             world,
             sleep_time,
+            error_handler,
         ));
 
         Self {
             threads: Vec::with_capacity(count),
             shared,
+            local: Vec::new(),
+            fixed_timesteps: AtomicUsize::new(0),
         }
     }
 
+    /// Checks that adding the executor keeps the dispatcher at a single
+    /// fixed-timestep group, returning the number of groups it adds.
+    fn check_fixed_timesteps(&self, executor: &SystemExecutor) -> usize {
+        let count = executor.fixed_timesteps();
+        assert_single_fixed_timestep(self.fixed_timesteps.load(Ordering::Acquire) + count);
+        count
+    }
+
+    /// Constructs the missing resources the executor fetches through
+    /// [`Init`](crate::Init).
+    fn init_missing(&self, executor: &SystemExecutor) {
+        let access = executor.access();
+
+        if !access.resources.inits.is_empty() {
+            access.resources.init_missing(&mut self.world());
+        }
+    }
+
+    /// Parks every dispatch thread, returning once they have all
+    /// finished executing their current system.
     fn park_all(&self) {
         self.shared.status.store(PARKED, Ordering::Release);
+
+        let backoff = Backoff::new();
+        while self.shared.parked.load(Ordering::Acquire) != self.threads.len() {
+            if backoff.is_completed() {
+                thread::sleep(Duration::from_millis(1));
+            } else {
+                backoff.snooze();
+            }
+        }
+    }
+
+    fn resume_all(&self) {
+        self.shared.status.store(RUNNING, Ordering::Release);
+        self.unpark_all();
     }
 
     fn unpark_all(&self) {
@@ -179,6 +532,37 @@ impl Dispatcher {
     }
 }
 
+/// The reasons [`Dispatcher::add_executor`] can reject an executor.
+#[derive(Debug)]
+pub enum AddExecutorError {
+    /// The executor requests conflicting borrows, so it would fail to
+    /// fetch its data every time it runs.
+    Access(AccessError),
+    /// The queue is full. The executor is returned.
+    QueueFull(SystemExecutor),
+}
+
+impl From<AccessError> for AddExecutorError {
+    fn from(error: AccessError) -> Self {
+        Self::Access(error)
+    }
+}
+
+impl fmt::Display for AddExecutorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Access(error) => error.fmt(f),
+            Self::QueueFull(executor) => {
+                write!(
+                    f,
+                    "The system queue is full, so {} was not added.",
+                    executor.name()
+                )
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SystemQueue {
     cache: AtomicRefCell<Option<SystemExecutor>>,
@@ -212,6 +596,18 @@ impl SystemQueue {
             .map_err(|PushError(executor)| executor)
     }
 
+    pub fn len(&self) -> usize {
+        let cached = self
+            .cache
+            .try_borrow()
+            .is_some_and(|cached| cached.is_some());
+        self.queue.len() + cached as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn pop(&self) -> Option<SystemExecutor> {
         match self.cache.try_borrow_mut() {
             Some(mut cached) if cached.is_some() => cached.take(),
@@ -220,6 +616,18 @@ impl SystemQueue {
     }
 }
 
+/// The default [`ErrorHandler`].
+fn log_error(system: &'static str, error: RetrievalError) {
+    eprintln!("System {} failed to run: {}", system, error);
+}
+
+fn assert_single_fixed_timestep(count: usize) {
+    assert!(
+        count <= 1,
+        "A dispatcher runs at most one FixedTimestep group, since every group updates the same FixedTime.",
+    );
+}
+
 const RUNNING: usize = 0;
 const PARKED: usize = 1;
 const SHUTDOWN: usize = 2;
@@ -276,41 +684,118 @@ impl DispatchThread {
     }
 }
 
+/// The state shared by the dispatch threads.
+///
+/// A tick ends once every system in the queue has run exactly once during
+/// it, however the systems are spread over the threads. The thread which
+/// finds no system left to run then starts the next tick by running the
+/// tick systems, and systems which already ran during the current tick
+/// are put back into the queue until it does.
 #[derive(Debug)]
 struct ThreadShared {
     status: AtomicUsize,
     parked: AtomicUsize,
     world: AtomicRefCell<World>,
     queue: SystemQueue,
+    tick: AtomicRefCell<SystemExecutor>,
+    // The number of ticks which have started.
+    ticks: AtomicU64,
+    // The number of systems in the queue.
+    systems: AtomicUsize,
+    // The number of systems which haven't run during the current tick.
+    remaining: AtomicUsize,
     sleep_time: Option<Duration>,
+    error_handler: ErrorHandler,
 }
 
 impl ThreadShared {
-    fn new(queue: SystemQueue, world: World, sleep_time: Option<Duration>) -> Self {
+    fn new(
+        queue: SystemQueue,
+        tick: SystemExecutor,
+        world: World,
+        sleep_time: Option<Duration>,
+        error_handler: ErrorHandler,
+    ) -> Self {
         Self {
             status: AtomicUsize::new(RUNNING),
             parked: AtomicUsize::new(0),
             world: AtomicRefCell::new(world),
             queue,
+            tick: AtomicRefCell::new(tick),
+            ticks: AtomicU64::new(0),
+            systems: AtomicUsize::new(0),
+            remaining: AtomicUsize::new(0),
             sleep_time,
+            error_handler,
+        }
+    }
+
+    /// Runs the executor, passing the error to the error handler if it
+    /// fails to fetch its data.
+    fn run(&self, executor: &mut SystemExecutor, world: &World) {
+        if let Err(error) = executor.execute(world) {
+            (self.error_handler)(executor.name(), error);
         }
     }
 
+    /// Runs the tick systems and starts the next tick, returning its
+    /// number.
+    fn start_tick(&self, tick: &mut SystemExecutor, world: &World) -> u64 {
+        self.run(tick, world);
+
+        // The count has to be reset before the tick is advanced, since
+        // systems only count down once they see the new tick.
+        self.remaining
+            .store(self.systems.load(Ordering::Acquire), Ordering::Release);
+        self.ticks.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
     fn execute(&self, backoff: &Backoff, sleep_time: Option<Duration>) {
         let world = self.world.borrow();
 
-        let resource_storage = world.resource_storage();
-        let component_storage = world.component_storage();
+        if self.remaining.load(Ordering::Acquire) == 0 {
+            // Another thread may have started the tick in the meantime,
+            // so the count is checked again once the tick systems are
+            // borrowed.
+            if let Some(mut tick) = self.tick.try_borrow_mut() {
+                if self.remaining.load(Ordering::Acquire) == 0 {
+                    self.start_tick(&mut tick, &world);
+                    backoff.reset();
+                    return;
+                }
+            }
+        }
 
         match self.queue.pop() {
             Some(mut executor) => {
+                let tick = self.ticks.load(Ordering::Acquire);
+
+                if executor.last_tick() == tick {
+                    // The system already ran during this tick. It goes to
+                    // the back of the queue, rather than into the cache, so
+                    // the systems which haven't run yet are reached. The
+                    // queue is only full if the cache is free.
+                    self.queue
+                        .push_no_cache(executor)
+                        .or_else(|executor| self.queue.push(executor))
+                        .expect("System queue was full.");
+
+                    if backoff.is_completed() {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+
+                    backoff.snooze();
+                    return;
+                }
+
                 if let Some(time) = sleep_time {
                     thread::sleep(time);
                 }
 
-                // FIXME: Do something with this Result.
-                executor.execute(resource_storage, component_storage).ok();
+                self.run(&mut executor, &world);
+                executor.set_last_tick(tick);
                 self.queue.push(executor).expect("System queue was full.");
+                self.remaining.fetch_sub(1, Ordering::AcqRel);
 
                 backoff.reset();
             }
@@ -329,7 +814,7 @@ impl ThreadShared {
 }
 
 #[derive(Debug)]
-pub struct WorldHandle<'a>(&'a Dispatcher, AtomicRefMut<'a, World>);
+pub struct WorldHandle<'a>(&'a Dispatcher, ManuallyDrop<AtomicRefMut<'a, World>>);
 
 impl Deref for WorldHandle<'_> {
     type Target = World;
@@ -347,6 +832,243 @@ impl DerefMut for WorldHandle<'_> {
 
 impl Drop for WorldHandle<'_> {
     fn drop(&mut self) {
-        self.0.unpark_all()
+        self.1.apply_commands();
+
+        // The borrow has to be released before the threads are resumed,
+        // otherwise they could try to borrow the world while it is
+        // still borrowed mutably.
+        unsafe {
+            ManuallyDrop::drop(&mut self.1);
+        }
+
+        self.0.resume_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::storage::{NonSendMut, ReadResource, WriteResource};
+    use crate::system::time::ManualClock;
+    use crate::system::RetrievalError;
+
+    use super::*;
+
+    /// Counts how many times it has run.
+    struct Count(Arc<AtomicU64>, Option<Duration>);
+
+    impl<'a> System<'a> for Count {
+        type Resources = ();
+        type Components = ();
+
+        fn execute(&mut self, _: (), _: ()) {
+            if let Some(time) = self.1 {
+                thread::sleep(time);
+            }
+
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    struct WriteTwice;
+
+    struct WriteMissing;
+
+    impl<'a> System<'a> for WriteMissing {
+        type Resources = (WriteResource<'a, u32>,);
+        type Components = ();
+
+        fn execute(&mut self, _: Self::Resources, _: ()) {}
+    }
+
+    impl<'a> System<'a> for WriteTwice {
+        type Resources = (WriteResource<'a, u32>, WriteResource<'a, u32>);
+        type Components = ();
+
+        fn execute(&mut self, _: Self::Resources, _: ()) {}
+    }
+
+    #[test]
+    fn rejects_conflicting_executors() {
+        assert!(DispatchBuilder::new()
+            .with_system(WriteTwice)
+            .build(World::new())
+            .is_err());
+
+        let mut dispatcher = DispatchBuilder::new()
+            .with_threads(0)
+            .build(World::new())
+            .unwrap();
+
+        assert!(matches!(
+            dispatcher.add_executor(SystemExecutor::new(WriteTwice)),
+            Err(AddExecutorError::Access(_)),
+        ));
+        assert!(dispatcher
+            .add_local_executor(SystemExecutor::new(WriteTwice))
+            .is_err());
+    }
+
+    #[test]
+    fn run_once_is_one_tick() {
+        let runs = Arc::new(AtomicU64::new(0));
+        let mut dispatcher = DispatchBuilder::new()
+            .with_system(Count(Arc::clone(&runs), None))
+            .with_threads(0)
+            .build(World::new())
+            .unwrap();
+
+        dispatcher.run_once();
+        dispatcher.run_once();
+
+        let world = dispatcher.world();
+        assert_eq!(world.get_resource::<Time>().unwrap().tick(), 2);
+        assert_eq!(runs.load(Ordering::Relaxed), 2);
+    }
+
+    /// Records the tick it ran in every time it runs.
+    struct Record(Arc<Mutex<Vec<u64>>>);
+
+    impl<'a> System<'a> for Record {
+        type Resources = (ReadResource<'a, Time>,);
+        type Components = ();
+
+        fn execute(&mut self, (time,): Self::Resources, _: ()) {
+            self.0.lock().push(time.tick());
+        }
+    }
+
+    #[test]
+    fn run_once_with_manual_clock() {
+        let clock = ManualClock::new();
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let mut dispatcher = DispatchBuilder::new()
+            .with_system(Record(Arc::clone(&ticks)))
+            .with_threads(0)
+            .with_clock(Clock::Manual(clock.clone()))
+            .build(World::new())
+            .unwrap();
+
+        for millis in 1..=3 {
+            clock.advance(Duration::from_millis(millis));
+            dispatcher.run_once();
+
+            let world = dispatcher.world();
+            let time = world.get_resource::<Time>().unwrap();
+            assert_eq!(time.delta(), Duration::from_millis(millis));
+            assert_eq!(time.tick(), millis);
+        }
+
+        assert_eq!(*ticks.lock(), [1, 2, 3]);
+        assert_eq!(
+            dispatcher.world().get_resource::<Time>().unwrap().elapsed(),
+            Duration::from_millis(6)
+        );
+    }
+
+    #[test]
+    fn systems_run_once_per_tick() {
+        let fast = Arc::new(Mutex::new(Vec::new()));
+        let slow = Arc::new(Mutex::new(Vec::new()));
+        let runs = Arc::new(AtomicU64::new(0));
+        let mut dispatcher = DispatchBuilder::new()
+            .with_system(Record(Arc::clone(&fast)))
+            .with_system(Record(Arc::clone(&slow)))
+            .with_system(Count(Arc::clone(&runs), Some(Duration::from_millis(1))))
+            .with_threads(2)
+            .with_clock(Clock::Manual(ManualClock::new()))
+            .build(World::new())
+            .unwrap();
+
+        dispatcher.dispatch();
+        while runs.load(Ordering::Relaxed) < 5 {
+            thread::yield_now();
+        }
+
+        {
+            // However the systems are spread over the threads, each one
+            // runs exactly once in every tick.
+            let world = dispatcher.world();
+            let tick = world.get_resource::<Time>().unwrap().tick();
+
+            for ticks in [&fast, &slow] {
+                let ticks = ticks.lock();
+                let expected: Vec<u64> = (1..=ticks.len() as u64).collect();
+                assert_eq!(*ticks, expected);
+                assert!(ticks.len() as u64 == tick || ticks.len() as u64 == tick - 1);
+            }
+        }
+
+        dispatcher.shutdown();
+    }
+
+    #[test]
+    fn reports_failed_systems() {
+        static FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+        fn count_failure(system: &'static str, error: RetrievalError) {
+            assert!(system.ends_with("WriteMissing"));
+            assert_eq!(error, RetrievalError::NoSuchResourceStorage);
+            FAILURES.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut dispatcher = DispatchBuilder::new()
+            .with_system(WriteMissing)
+            .with_threads(0)
+            .with_error_handler(count_failure)
+            .build(World::new())
+            .unwrap();
+
+        dispatcher.run_once();
+        dispatcher.run_once();
+        assert_eq!(FAILURES.load(Ordering::Relaxed), 2);
+    }
+
+    struct Bump;
+
+    impl<'a> System<'a> for Bump {
+        type Resources = (NonSendMut<'a, Rc<Cell<u32>>>,);
+        type Components = ();
+
+        fn execute(&mut self, (counter,): Self::Resources, _: ()) {
+            counter.set(counter.get() + 1);
+        }
+    }
+
+    #[test]
+    fn local_systems_run_on_the_owning_thread() {
+        let world = World::new();
+
+        // The dispatcher is created on another thread than the world, and
+        // owns its non-Send resources from then on.
+        thread::spawn(move || {
+            let mut dispatcher = DispatchBuilder::new()
+                .with_system(Bump)
+                .with_threads(0)
+                .build(world)
+                .unwrap();
+
+            let counter = Rc::new(Cell::new(0u32));
+            dispatcher.world().insert_non_send(Rc::clone(&counter));
+            dispatcher.run_once();
+            dispatcher.run_local();
+            assert_eq!(counter.get(), 2);
+
+            let world = dispatcher.world();
+            let world: &World = &world;
+            thread::scope(|scope| {
+                let result = scope
+                    .spawn(|| SystemExecutor::new(Bump).execute(world))
+                    .join()
+                    .unwrap();
+                assert_eq!(result, Err(RetrievalError::NonSendOffThread));
+            });
+        })
+        .join()
+        .unwrap();
     }
 }
@@ -1,13 +1,28 @@
-use std::fmt;
+use std::{
+    any::{self, Any},
+    fmt,
+};
 
-use parking_lot::RwLock;
-
-use crate::storage::{ComponentStorageAllocator, ResourceStorageAllocator};
-use crate::system::{ComponentData, ResourceData, RetrievalError, System};
+use crate::system::{
+    self,
+    fixed::{FixedTime, FixedTimestep},
+    AccessError, ResourceData, RetrievalError, System, SystemAccess,
+};
+use crate::utils;
+use crate::world::World;
 
 #[derive(Debug)]
 pub struct SystemExecutor {
-    raw: RawExecutor,
+    kind: ExecutorKind,
+    // The tick of the dispatcher the executor last ran in.
+    last_tick: u64,
+}
+
+#[derive(Debug)]
+enum ExecutorKind {
+    System(RawExecutor),
+    FixedTimestep(FixedTimestep),
+    Sequence(Vec<SystemExecutor>),
 }
 
 impl SystemExecutor {
@@ -16,16 +31,131 @@ impl SystemExecutor {
         S: for<'a> System<'a> + Send + Sync,
     {
         Self {
-            raw: RawExecutor::new(system),
+            kind: ExecutorKind::System(RawExecutor::new(system)),
+            last_tick: 0,
+        }
+    }
+
+    /// Constructs an executor which runs every system in the group
+    /// at the group's fixed rate.
+    pub fn from_fixed_timestep(group: FixedTimestep) -> Self {
+        Self {
+            kind: ExecutorKind::FixedTimestep(group),
+            last_tick: 0,
+        }
+    }
+
+    /// Constructs an executor which runs every system once, in order.
+    pub fn sequence(systems: Vec<SystemExecutor>) -> Self {
+        Self {
+            kind: ExecutorKind::Sequence(systems),
+            last_tick: 0,
+        }
+    }
+
+    pub fn is_fixed_timestep(&self) -> bool {
+        matches!(self.kind, ExecutorKind::FixedTimestep(_))
+    }
+
+    /// Returns whether the executor borrows any non-Send resources, and
+    /// so may only be run on the thread which owns the world.
+    pub fn is_non_send(&self) -> bool {
+        self.access().resources.is_non_send()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match &self.kind {
+            ExecutorKind::System(raw) => raw.name(),
+            ExecutorKind::FixedTimestep(_) => any::type_name::<FixedTimestep>(),
+            ExecutorKind::Sequence(_) => "sequence",
+        }
+    }
+
+    /// The tick of the dispatcher the executor last ran in, or `0` if it
+    /// has never run.
+    pub(crate) fn last_tick(&self) -> u64 {
+        self.last_tick
+    }
+
+    pub(crate) fn set_last_tick(&mut self, tick: u64) {
+        self.last_tick = tick;
+    }
+
+    /// The number of fixed-timestep groups in the executor, including
+    /// groups nested in other groups.
+    pub(crate) fn fixed_timesteps(&self) -> usize {
+        let nested: usize = self
+            .group()
+            .unwrap_or_default()
+            .iter()
+            .map(SystemExecutor::fixed_timesteps)
+            .sum();
+
+        nested + self.is_fixed_timestep() as usize
+    }
+
+    /// Returns the systems run by this executor if it is a group.
+    fn group(&self) -> Option<&[SystemExecutor]> {
+        match &self.kind {
+            ExecutorKind::System(_) => None,
+            ExecutorKind::FixedTimestep(group) => Some(group.systems()),
+            ExecutorKind::Sequence(systems) => Some(systems),
+        }
+    }
+
+    /// Returns the resources and components borrowed by the system. For
+    /// groups, this is the union of the borrows of every system in the
+    /// group.
+    pub fn access(&self) -> SystemAccess {
+        if let ExecutorKind::System(raw) = &self.kind {
+            return raw.access();
+        }
+
+        let mut access = SystemAccess::default();
+
+        for executor in self.group().unwrap_or_default() {
+            let other = executor.access();
+            access.resources.extend(&other.resources);
+            access.components.extend(&other.components);
+        }
+
+        if self.is_fixed_timestep() {
+            // The group updates `FixedTime` itself once its systems ran.
+            access.resources.write::<FixedTime>();
+            access.resources.init::<FixedTime>();
+        }
+
+        access
+    }
+
+    /// Checks that the system never borrows the same resource or
+    /// component mutably alongside another borrow of it. A system
+    /// which fails this check would fail to fetch its data every
+    /// time it is executed. The systems of a group are checked one by
+    /// one, since they run one after another.
+    pub fn validate(&self) -> Result<(), AccessError> {
+        if let Some(group) = self.group() {
+            return group.iter().try_for_each(SystemExecutor::validate);
         }
+
+        self.access().validate(self.name())
     }
 
-    pub fn execute(
-        &mut self,
-        resources: &RwLock<ResourceStorageAllocator>,
-        components: &RwLock<ComponentStorageAllocator>,
-    ) -> Result<(), RetrievalError> {
-        self.raw.execute(resources, components)
+    pub fn execute(&mut self, world: &World) -> Result<(), RetrievalError> {
+        match &mut self.kind {
+            ExecutorKind::System(raw) => raw.execute(world),
+            ExecutorKind::FixedTimestep(group) => group.execute(world),
+            ExecutorKind::Sequence(systems) => {
+                let mut result = Ok(());
+
+                for executor in systems.iter_mut() {
+                    let next = executor.execute(world);
+                    result = result.and(next);
+                }
+
+                result
+            }
+        }
     }
 }
 
@@ -42,20 +172,27 @@ impl RawExecutor {
         let vtable = &ExecutorVTable {
             execute: ExecutorVTable::execute::<S>,
             drop: ExecutorVTable::drop::<S>,
+            name: any::type_name::<S>,
+            access: SystemAccess::of::<S>,
         };
 
-        let inner =
-            Box::into_raw(Box::new(Inner::new(vtable, system))) as *mut &'static ExecutorVTable;
+        let state = Box::new(<S::Resources as ResourceData<'_>>::init_state());
+        let inner = Box::into_raw(Box::new(Inner::new(vtable, system, state)))
+            as *mut &'static ExecutorVTable;
 
         Self { inner }
     }
 
-    pub fn execute(
-        &mut self,
-        resources: &RwLock<ResourceStorageAllocator>,
-        components: &RwLock<ComponentStorageAllocator>,
-    ) -> Result<(), RetrievalError> {
-        unsafe { ((*self.inner).execute)(self.inner, resources, components) }
+    pub fn execute(&mut self, world: &World) -> Result<(), RetrievalError> {
+        unsafe { ((*self.inner).execute)(self.inner, world) }
+    }
+
+    pub fn name(&self) -> &'static str {
+        unsafe { ((*self.inner).name)() }
+    }
+
+    pub fn access(&self) -> SystemAccess {
+        unsafe { ((*self.inner).access)() }
     }
 }
 
@@ -77,67 +214,265 @@ where
 {
     vtable: &'static ExecutorVTable,
     system: S,
+    // The state of the system's resources. This is type-erased because
+    // the state type can only be named through one of the lifetimes the
+    // system is implemented for, even though it doesn't depend on it.
+    state: Box<dyn Any + Send + Sync>,
+    // The change tick of the system's last successful run.
+    last_run: u64,
 }
 
 impl<S> Inner<S>
 where
     S: for<'a> System<'a> + Send + Sync,
 {
-    pub fn new(vtable: &'static ExecutorVTable, system: S) -> Self {
-        Self { vtable, system }
+    pub fn new(
+        vtable: &'static ExecutorVTable,
+        system: S,
+        state: Box<dyn Any + Send + Sync>,
+    ) -> Self {
+        Self {
+            vtable,
+            system,
+            state,
+            last_run: 0,
+        }
     }
 }
 
+type ExecuteFn = unsafe fn(*mut &'static ExecutorVTable, &World) -> Result<(), RetrievalError>;
+
 pub struct ExecutorVTable {
     /// This function will cast the vtable into an Inner<S> instance.
-    execute: unsafe fn(
-        *mut &'static Self,
-        &RwLock<ResourceStorageAllocator>,
-        &RwLock<ComponentStorageAllocator>,
-    ) -> Result<(), RetrievalError>,
+    execute: ExecuteFn,
 
     drop: unsafe fn(*mut &'static Self),
+
+    name: fn() -> &'static str,
+
+    access: fn() -> SystemAccess,
 }
 
 impl ExecutorVTable {
-    pub unsafe fn execute<S>(
-        ptr: *mut &'static Self,
-        resource_alloc: &RwLock<ResourceStorageAllocator>,
-        component_alloc: &RwLock<ComponentStorageAllocator>,
-    ) -> Result<(), RetrievalError>
+    /// # Safety
+    ///
+    /// `ptr` must point to a live `Inner<S>` created by `RawExecutor::new`.
+    pub unsafe fn execute<S>(ptr: *mut &'static Self, world: &World) -> Result<(), RetrievalError>
     where
         S: for<'a> System<'a> + Send + Sync,
     {
-        let inner = ptr as *mut Inner<S>;
+        let inner = &mut *(ptr as *mut Inner<S>);
 
-        let resource_guard = resource_alloc
+        let resource_guard = world
+            .resource_storage()
             .try_read()
             .ok_or(RetrievalError::ResourceLockedExclusive)?;
-        let component_guard = component_alloc
+        let component_guard = world
+            .component_storage()
             .try_read()
             .ok_or(RetrievalError::ComponentLockedExclusive)?;
 
-        let resources = S::Resources::fetch(&resource_guard)?;
-        let components = S::Components::fetch(&component_guard)?;
+        let state = inner
+            .state
+            .downcast_mut::<<S::Resources as ResourceData<'_>>::State>()
+            .unwrap_or_else(|| {
+                utils::debug_unreachable("System state did not match its resource state type.")
+            });
 
-        (*inner).system.execute(resources, components);
+        let (resources, components, ticks) = system::fetch::<S::Resources, S::Components>(
+            world,
+            &resource_guard,
+            &component_guard,
+            state,
+            inner.last_run,
+        )?;
+
+        inner.system.execute(resources, components);
+        inner.last_run = ticks.this_run();
 
         Ok(())
     }
 
+    /// # Safety
+    ///
+    /// `ptr` must point to a live `Inner<S>` created by `RawExecutor::new`,
+    /// and must not be used after this call.
     pub unsafe fn drop<S>(ptr: *mut &'static Self)
     where
         S: for<'a> System<'a> + Send + Sync,
     {
-        Box::from_raw(ptr as *mut Inner<S>);
+        drop(Box::from_raw(ptr as *mut Inner<S>));
     }
 }
 
 impl fmt::Debug for ExecutorVTable {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ExecutorVTable")
             .field("execute", &(self.execute as *const ()))
             .field("drop", &(self.drop as *const ()))
+            .field("name", &(self.name)())
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        change::{Added, Changed},
+        entity::EntityId,
+        storage::{ReadResource, WriteComponent, WriteResource},
+    };
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Position(u32);
+
+    /// The entities a [`Detect`] run saw as added, and as changed.
+    type Seen = Arc<Mutex<(Vec<EntityId>, Vec<EntityId>)>>;
+
+    struct Detect(Seen);
+
+    impl<'a> System<'a> for Detect {
+        type Resources = ();
+        type Components = (Added<'a, Position>, Changed<'a, Position>);
+
+        fn execute(&mut self, _: (), (added, changed): Self::Components) {
+            let mut seen = self.0.lock().unwrap();
+            seen.0 = added.iter().map(|(id, _)| id).collect();
+            seen.1 = changed.iter().map(|(id, _)| id).collect();
+        }
+    }
+
+    struct Move(EntityId);
+
+    impl<'a> System<'a> for Move {
+        type Resources = ();
+        type Components = WriteComponent<'a, Position>;
+
+        fn execute(&mut self, _: (), mut positions: Self::Components) {
+            positions.get_mut(self.0).unwrap().0 += 1;
+        }
+    }
+
+    struct NeedsMissing;
+
+    impl<'a> System<'a> for NeedsMissing {
+        type Resources = ReadResource<'a, u32>;
+        type Components = WriteComponent<'a, Position>;
+
+        fn execute(&mut self, _: Self::Resources, _: Self::Components) {
+            unreachable!();
+        }
+    }
+
+    struct ReadWrite;
+
+    impl<'a> System<'a> for ReadWrite {
+        type Resources = (ReadResource<'a, u32>, WriteResource<'a, u32>);
+        type Components = ();
+
+        fn execute(&mut self, _: Self::Resources, _: ()) {}
+    }
+
+    struct WriteWrite;
+
+    impl<'a> System<'a> for WriteWrite {
+        type Resources = ();
+        type Components = (WriteComponent<'a, Position>, WriteComponent<'a, Position>);
+
+        fn execute(&mut self, _: (), _: Self::Components) {}
+    }
+
+    #[test]
+    fn conflicting_borrows() {
+        assert_eq!(
+            SystemExecutor::new(ReadWrite).validate(),
+            Err(AccessError::ConflictingResourceAccess {
+                system: any::type_name::<ReadWrite>(),
+                resource: any::type_name::<u32>(),
+            }),
+        );
+        assert_eq!(
+            SystemExecutor::new(WriteWrite).validate(),
+            Err(AccessError::ConflictingComponentAccess {
+                system: any::type_name::<WriteWrite>(),
+                component: any::type_name::<Position>(),
+            }),
+        );
+    }
+
+    #[test]
+    fn conflicts_in_groups() {
+        let entity = EntityId::default();
+        let error = Err(AccessError::ConflictingResourceAccess {
+            system: any::type_name::<ReadWrite>(),
+            resource: any::type_name::<u32>(),
+        });
+
+        let sequence = SystemExecutor::sequence(vec![
+            SystemExecutor::new(Move(entity)),
+            SystemExecutor::new(ReadWrite),
+        ]);
+        assert_eq!(sequence.validate(), error);
+        assert!(sequence.access().resources.conflict().is_some());
+
+        let fixed = FixedTimestep::from_rate(60.0).with_system(ReadWrite);
+        assert_eq!(SystemExecutor::from_fixed_timestep(fixed).validate(), error);
+
+        // Systems of a group run one after another, so one may write what
+        // another reads.
+        let seen = Seen::default();
+        let sequence = SystemExecutor::sequence(vec![
+            SystemExecutor::new(Move(entity)),
+            SystemExecutor::new(Detect(seen)),
+        ]);
+        assert_eq!(sequence.validate(), Ok(()));
+        assert!(sequence.access().components.conflict().is_none());
+    }
+
+    #[test]
+    fn added_and_changed() {
+        let mut world = World::new();
+        let entity = world.create_entity(Position(0)).id();
+
+        let seen = Seen::default();
+        let mut detect = SystemExecutor::new(Detect(Arc::clone(&seen)));
+        let mut moved = SystemExecutor::new(Move(entity));
+
+        detect.execute(&world).unwrap();
+        assert_eq!(*seen.lock().unwrap(), (vec![entity], vec![entity]));
+
+        detect.execute(&world).unwrap();
+        assert_eq!(*seen.lock().unwrap(), (vec![], vec![]));
+
+        moved.execute(&world).unwrap();
+        detect.execute(&world).unwrap();
+        assert_eq!(*seen.lock().unwrap(), (vec![], vec![entity]));
+
+        detect.execute(&world).unwrap();
+        assert_eq!(*seen.lock().unwrap(), (vec![], vec![]));
+    }
+
+    #[test]
+    fn failed_fetch_keeps_tick() {
+        let mut world = World::new();
+        world.create_entity(Position(0));
+
+        let tick = world.change_tick();
+        let mut failing = SystemExecutor::new(NeedsMissing);
+
+        assert!(failing.execute(&world).is_err());
+        assert_eq!(world.change_tick(), tick);
+
+        // The next system to run claims the tick instead.
+        let seen = Seen::default();
+        let mut detect = SystemExecutor::new(Detect(Arc::clone(&seen)));
+
+        detect.execute(&world).unwrap();
+        assert_eq!(seen.lock().unwrap().1.len(), 1);
+        assert_eq!(world.change_tick(), tick + 1);
+    }
+}
@@ -0,0 +1,343 @@
+use std::time::Duration;
+
+use crate::system::{executor::SystemExecutor, time::Time, RetrievalError, System};
+use crate::world::World;
+
+const DEFAULT_MAX_STEPS: u32 = 5;
+
+/// The resource updated by a [`FixedTimestep`] group every time it is
+/// executed. Systems outside of the group can use the interpolation
+/// alpha to blend between the previous and current fixed step.
+///
+/// There is only one `FixedTime` per world, so a dispatcher only runs a
+/// single fixed-timestep group.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct FixedTime {
+    step: Duration,
+    alpha: f64,
+    steps: u32,
+}
+
+impl FixedTime {
+    /// The duration of a single fixed step.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// The fraction of a step left in the accumulator after the group
+    /// last ran, in the range `[0, 1)`.
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// The number of steps which were run the last time the group ran.
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+}
+
+/// A group of systems which are run zero or more times each time the
+/// group is executed by the dispatcher, depending on how much scaled
+/// [`Time`] has passed since it was last executed.
+#[derive(Debug)]
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+    last: Option<Duration>,
+    systems: Vec<SystemExecutor>,
+}
+
+impl FixedTimestep {
+    /// Constructs a group which runs once every `step`.
+    pub fn new(step: Duration) -> Self {
+        assert!(
+            step > Duration::from_secs(0),
+            "FixedTimestep step must be non-zero."
+        );
+
+        Self {
+            step,
+            max_steps: DEFAULT_MAX_STEPS,
+            accumulator: Duration::from_secs(0),
+            last: None,
+            systems: Vec::new(),
+        }
+    }
+
+    /// Constructs a group which runs `rate` times per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` isn't positive and finite, or is so large that a
+    /// step rounds down to zero.
+    pub fn from_rate(rate: f64) -> Self {
+        assert!(
+            rate > 0.0 && rate.is_finite(),
+            "FixedTimestep rate must be positive and finite, but was {}.",
+            rate,
+        );
+
+        Self::new(Duration::from_secs_f64(1.0 / rate))
+    }
+
+    /// Sets the maximum number of steps run per execution of the group.
+    /// Any time accumulated beyond this is discarded, which keeps a slow
+    /// step from causing every following execution to fall further behind.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_steps` is zero, since the group would never run.
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        assert!(max_steps > 0, "FixedTimestep max steps must be non-zero.");
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_system<S>(mut self, system: S) -> Self
+    where
+        S: for<'a> System<'a> + Send + Sync,
+    {
+        self.systems.push(SystemExecutor::new(system));
+        self
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn systems(&self) -> &[SystemExecutor] {
+        &self.systems
+    }
+
+    /// Runs every system in the group once for each step that has
+    /// accumulated since the group was last executed, then updates the
+    /// [`FixedTime`] resource. Returns the first error encountered, but
+    /// always attempts to run every step, and to update `FixedTime`.
+    pub fn execute(&mut self, world: &World) -> Result<(), RetrievalError> {
+        let resources = world.resource_storage();
+
+        let now = {
+            let guard = resources
+                .try_read()
+                .ok_or(RetrievalError::ResourceLockedExclusive)?;
+
+            if !guard.contains::<Time>() {
+                return Err(RetrievalError::NoSuchResourceStorage);
+            }
+
+            let time = guard
+                .try_get::<Time>()
+                .ok_or(RetrievalError::ResourceStorageInUse)?;
+
+            time.elapsed()
+        };
+
+        if let Some(last) = self.last.replace(now) {
+            self.accumulator += now - last;
+        }
+
+        let max = self
+            .step
+            .checked_mul(self.max_steps)
+            .unwrap_or(Duration::MAX);
+        if self.accumulator > max {
+            self.accumulator = max;
+        }
+
+        let mut result = Ok(());
+        let mut steps = 0;
+
+        while self.accumulator >= self.step {
+            for executor in self.systems.iter_mut() {
+                let step_result = executor.execute(world);
+                result = result.and(step_result);
+            }
+
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        let time = FixedTime {
+            step: self.step,
+            alpha: self.accumulator.as_secs_f64() / self.step.as_secs_f64(),
+            steps,
+        };
+
+        self.write_time(world, time).and(result)
+    }
+
+    fn write_time(&self, world: &World, time: FixedTime) -> Result<(), RetrievalError> {
+        let guard = world
+            .resource_storage()
+            .try_read()
+            .ok_or(RetrievalError::ResourceLockedExclusive)?;
+
+        if !guard.contains::<FixedTime>() {
+            return Err(RetrievalError::NoSuchResourceStorage);
+        }
+
+        let mut storage = guard
+            .try_get_mut::<FixedTime>()
+            .ok_or(RetrievalError::ResourceStorageInUse)?;
+
+        **storage = time;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use crate::system::dispatch::Dispatcher;
+
+    use super::*;
+
+    struct Count(Arc<AtomicU32>);
+
+    impl<'a> System<'a> for Count {
+        type Resources = ();
+        type Components = ();
+
+        fn execute(&mut self, _: (), _: ()) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Time::new());
+        world.insert_resource(FixedTime::default());
+        world
+    }
+
+    fn advance(world: &mut World, millis: u64) {
+        let mut time = world.get_resource_mut::<Time>().unwrap();
+        time.advance(Duration::from_millis(millis));
+    }
+
+    fn fixed_time(world: &World) -> FixedTime {
+        **world.get_resource::<FixedTime>().unwrap()
+    }
+
+    #[test]
+    fn accumulates_steps() {
+        let mut world = world();
+        let runs = Arc::new(AtomicU32::new(0));
+        let mut group =
+            FixedTimestep::new(Duration::from_millis(10)).with_system(Count(Arc::clone(&runs)));
+
+        // The first execution only starts the clock.
+        advance(&mut world, 100);
+        group.execute(&world).unwrap();
+        assert_eq!(runs.load(Ordering::Relaxed), 0);
+
+        advance(&mut world, 25);
+        group.execute(&world).unwrap();
+        assert_eq!(runs.load(Ordering::Relaxed), 2);
+
+        let time = fixed_time(&world);
+        assert_eq!(time.step(), Duration::from_millis(10));
+        assert_eq!(time.steps(), 2);
+        assert!((time.alpha() - 0.5).abs() < 1e-9);
+
+        // The leftover half step counts towards the next execution.
+        advance(&mut world, 5);
+        group.execute(&world).unwrap();
+        assert_eq!(runs.load(Ordering::Relaxed), 3);
+        assert_eq!(fixed_time(&world).steps(), 1);
+        assert!(fixed_time(&world).alpha() < 1e-9);
+    }
+
+    #[test]
+    fn clamps_steps() {
+        let mut world = world();
+        let runs = Arc::new(AtomicU32::new(0));
+        let mut group = FixedTimestep::new(Duration::from_millis(10))
+            .with_max_steps(3)
+            .with_system(Count(Arc::clone(&runs)));
+
+        group.execute(&world).unwrap();
+        advance(&mut world, 1000);
+        group.execute(&world).unwrap();
+
+        assert_eq!(runs.load(Ordering::Relaxed), 3);
+        assert_eq!(fixed_time(&world).steps(), 3);
+        assert!(fixed_time(&world).alpha() < 1e-9);
+
+        // A maximum which overflows the step saturates instead.
+        let mut group = FixedTimestep::new(Duration::from_secs(u64::MAX / 2)).with_max_steps(3);
+        group.execute(&world).unwrap();
+        assert_eq!(fixed_time(&world).steps(), 0);
+    }
+
+    #[test]
+    fn reports_a_missing_fixed_time() {
+        let mut world = World::new();
+        world.insert_resource(Time::new());
+
+        let mut group = FixedTimestep::new(Duration::from_millis(10));
+        assert_eq!(
+            group.execute(&world),
+            Err(RetrievalError::NoSuchResourceStorage)
+        );
+    }
+
+    #[test]
+    fn added_groups_get_fixed_time() {
+        let mut dispatcher = Dispatcher::new(World::new(), 1);
+        let group = FixedTimestep::from_rate(60.0);
+
+        dispatcher
+            .add_executor(SystemExecutor::from_fixed_timestep(group))
+            .unwrap();
+        dispatcher.run_once();
+
+        assert!(dispatcher.world().contains_resource::<FixedTime>());
+    }
+
+    #[test]
+    #[should_panic(expected = "runs at most one FixedTimestep group")]
+    fn rejects_a_second_group() {
+        let dispatcher = Dispatcher::new(World::new(), 2);
+
+        for _ in 0..2 {
+            let group = SystemExecutor::from_fixed_timestep(FixedTimestep::from_rate(60.0));
+            dispatcher.add_executor(group).unwrap();
+        }
+    }
+
+    #[test]
+    fn from_rate() {
+        let step = FixedTimestep::from_rate(4.0).step();
+        assert_eq!(step, Duration::from_millis(250));
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be positive and finite")]
+    fn from_rate_rejects_zero() {
+        FixedTimestep::from_rate(0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be positive and finite")]
+    fn from_rate_rejects_nan() {
+        FixedTimestep::from_rate(f64::NAN);
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be positive and finite")]
+    fn from_rate_rejects_infinity() {
+        FixedTimestep::from_rate(f64::INFINITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "max steps must be non-zero")]
+    fn rejects_zero_max_steps() {
+        FixedTimestep::from_rate(60.0).with_max_steps(0);
+    }
+}
@@ -0,0 +1,151 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::{
+    change::SystemTicks,
+    resource::Resource,
+    storage::{ReadResource, ResourceStorageAllocator, WriteResource},
+    system::{Access, ResourceData, RetrievalError},
+    world::FromWorld,
+};
+
+/// Fetches a resource like `R`, but asks the dispatcher to construct the
+/// resource with [`FromWorld`] if the world doesn't contain it before
+/// the system first runs, instead of failing to fetch it every tick.
+///
+/// `R` is either a [`ReadResource`] or a [`WriteResource`].
+#[derive(Debug)]
+pub struct Init<R> {
+    resource: R,
+}
+
+impl<R> Init<R> {
+    pub fn into_inner(self) -> R {
+        self.resource
+    }
+}
+
+impl<R> Deref for Init<R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        &self.resource
+    }
+}
+
+impl<R> DerefMut for Init<R> {
+    fn deref_mut(&mut self) -> &mut R {
+        &mut self.resource
+    }
+}
+
+impl<'a, T: Resource + FromWorld> ResourceData<'a> for Init<ReadResource<'a, T>> {
+    type State = ();
+
+    fn init_state() {}
+
+    fn fetch(
+        allocator: &'a ResourceStorageAllocator,
+        state: &'a mut (),
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        ReadResource::fetch(allocator, state, ticks).map(|resource| Self { resource })
+    }
+
+    fn access(access: &mut Access) {
+        ReadResource::<T>::access(access);
+        access.init::<T>();
+    }
+}
+
+impl<'a, T: Resource + FromWorld> ResourceData<'a> for Init<WriteResource<'a, T>> {
+    type State = ();
+
+    fn init_state() {}
+
+    fn fetch(
+        allocator: &'a ResourceStorageAllocator,
+        state: &'a mut (),
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        WriteResource::fetch(allocator, state, ticks).map(|resource| Self { resource })
+    }
+
+    fn access(access: &mut Access) {
+        WriteResource::<T>::access(access);
+        access.init::<T>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        system::{dispatch::DispatchBuilder, executor::SystemExecutor},
+        world::World,
+        System,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Runs(u32);
+
+    #[derive(Debug)]
+    struct Limit(u32);
+
+    impl FromWorld for Limit {
+        fn from_world(world: &mut World) -> Self {
+            Limit(world.get_resource::<u32>().map_or(0, |base| **base * 2))
+        }
+    }
+
+    struct Count;
+
+    impl<'a> System<'a> for Count {
+        type Resources = (Init<WriteResource<'a, Runs>>, Init<ReadResource<'a, Limit>>);
+        type Components = ();
+
+        fn execute(&mut self, (mut runs, limit): Self::Resources, _: ()) {
+            if runs.0 < limit.0 {
+                runs.0 += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn constructs_missing_resources() {
+        let mut world = World::new();
+        world.insert_resource(1u32);
+
+        let mut dispatcher = DispatchBuilder::new()
+            .with_system(Count)
+            .with_threads(0)
+            .build(world)
+            .unwrap();
+
+        for _ in 0..3 {
+            dispatcher.run_once();
+        }
+
+        let world = dispatcher.world();
+        assert_eq!(world.get_resource::<Limit>().unwrap().0, 2);
+        assert_eq!(world.get_resource::<Runs>().unwrap().0, 2);
+    }
+
+    #[test]
+    fn keeps_existing_resources() {
+        let mut world = World::new();
+        world.insert_resource(Limit(1));
+
+        let mut dispatcher = DispatchBuilder::new().with_threads(0).build(world).unwrap();
+        dispatcher
+            .add_local_executor(SystemExecutor::new(Count))
+            .unwrap();
+
+        dispatcher.run_once();
+        dispatcher.run_once();
+
+        let world = dispatcher.world();
+        assert_eq!(world.get_resource::<Limit>().unwrap().0, 1);
+        assert_eq!(world.get_resource::<Runs>().unwrap().0, 1);
+    }
+}
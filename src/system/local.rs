@@ -0,0 +1,107 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::{
+    change::SystemTicks,
+    storage::ResourceStorageAllocator,
+    system::{Access, ResourceData, RetrievalError},
+};
+
+/// A value private to the system which fetches it, kept between runs of
+/// that system. The value is stored with the system itself rather than
+/// in the world, so no other system can see or contend for it. Each
+/// `Local` in a system's resources is a separate value, starting out as
+/// `T::default()`.
+#[derive(Debug)]
+pub struct Local<'a, T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    value: &'a mut T,
+}
+
+impl<T> Deref for Local<'_, T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Local<'_, T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T> ResourceData<'a> for Local<'a, T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    type State = T;
+
+    fn init_state() -> T {
+        T::default()
+    }
+
+    fn fetch(
+        _: &'a ResourceStorageAllocator,
+        value: &'a mut T,
+        _: SystemTicks,
+    ) -> Result<Self, RetrievalError> {
+        Ok(Self { value })
+    }
+
+    // A local is never shared, so it can't conflict with anything.
+    fn access(_: &mut Access) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{system::executor::SystemExecutor, world::World, System};
+
+    use super::*;
+
+    /// Counts its own runs in one local, and every other run in another.
+    struct Counter(Arc<Mutex<Vec<(u32, u32)>>>);
+
+    impl<'a> System<'a> for Counter {
+        type Resources = (Local<'a, u32>, Local<'a, u32>);
+        type Components = ();
+
+        fn execute(&mut self, (mut runs, mut halves): Self::Resources, _: ()) {
+            *runs += 1;
+            if *runs % 2 == 0 {
+                *halves += 1;
+            }
+
+            self.0.lock().push((*runs, *halves));
+        }
+    }
+
+    #[test]
+    fn locals_persist_per_system() {
+        let world = World::new();
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        let mut a = SystemExecutor::new(Counter(Arc::clone(&first)));
+        let mut b = SystemExecutor::new(Counter(Arc::clone(&second)));
+
+        for _ in 0..3 {
+            a.execute(&world).unwrap();
+        }
+        b.execute(&world).unwrap();
+
+        assert_eq!(*first.lock(), [(1, 0), (2, 1), (3, 1)]);
+        assert_eq!(*second.lock(), [(1, 0)]);
+    }
+}
@@ -1,22 +1,56 @@
-use std::fmt;
+use std::{
+    any::{self, TypeId},
+    fmt,
+};
 
+use crate::change::SystemTicks;
+use crate::resource::Resource;
 use crate::storage::{ComponentStorageAllocator, ResourceStorageAllocator};
+use crate::world::{FromWorld, World};
 
 pub mod dispatch;
 pub mod executor;
+pub mod fixed;
+pub mod init;
+pub mod local;
+pub mod time;
 
 pub trait ResourceData<'a>
 where
     Self: Sized + 'a,
 {
-    fn fetch(allocator: &'a ResourceStorageAllocator) -> Result<Self, RetrievalError>;
+    /// State owned by the system fetching this type, kept between runs
+    /// of the system. Types which need no state use `()`.
+    type State: Send + Sync + 'static;
+
+    /// Constructs the state before the system's first run.
+    fn init_state() -> Self::State;
+
+    /// Fetches the resources. `ticks` are the change ticks of the
+    /// fetching system, used for change detection.
+    fn fetch(
+        allocator: &'a ResourceStorageAllocator,
+        state: &'a mut Self::State,
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError>;
+
+    /// Records every resource this type borrows into `access`.
+    fn access(access: &mut Access);
 }
 
 pub trait ComponentData<'a>
 where
     Self: Sized + 'a,
 {
-    fn fetch(allocator: &'a ComponentStorageAllocator) -> Result<Self, RetrievalError>;
+    /// Fetches the components. `ticks` are the change ticks of the
+    /// fetching system, used by change detection filters.
+    fn fetch(
+        allocator: &'a ComponentStorageAllocator,
+        ticks: SystemTicks,
+    ) -> Result<Self, RetrievalError>;
+
+    /// Records every component storage this type borrows into `access`.
+    fn access(access: &mut Access);
 }
 
 pub trait System<'a> {
@@ -26,12 +60,230 @@ pub trait System<'a> {
     fn execute(&mut self, _: Self::Resources, _: Self::Components);
 }
 
+/// The set of types a system borrows from a single allocator, used to
+/// detect a system which would always fail to fetch its own data.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+    non_send_reads: Vec<TypeId>,
+    non_send_writes: Vec<TypeId>,
+    inits: Vec<fn(&mut World)>,
+    conflict: Option<&'static str>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a shared borrow of `T`.
+    pub fn read<T: 'static>(&mut self) {
+        let type_id = TypeId::of::<T>();
+
+        if self.writes.contains(&type_id) {
+            self.set_conflict::<T>();
+        }
+
+        self.reads.push(type_id);
+    }
+
+    /// Records an exclusive borrow of `T`.
+    pub fn write<T: 'static>(&mut self) {
+        let type_id = TypeId::of::<T>();
+
+        if self.reads.contains(&type_id) || self.writes.contains(&type_id) {
+            self.set_conflict::<T>();
+        }
+
+        self.writes.push(type_id);
+    }
+
+    /// Records a shared borrow of the non-Send resource `T`.
+    pub fn read_non_send<T: 'static>(&mut self) {
+        let type_id = TypeId::of::<T>();
+
+        if self.non_send_writes.contains(&type_id) {
+            self.set_conflict::<T>();
+        }
+
+        self.non_send_reads.push(type_id);
+    }
+
+    /// Records an exclusive borrow of the non-Send resource `T`.
+    pub fn write_non_send<T: 'static>(&mut self) {
+        let type_id = TypeId::of::<T>();
+
+        if self.non_send_reads.contains(&type_id) || self.non_send_writes.contains(&type_id) {
+            self.set_conflict::<T>();
+        }
+
+        self.non_send_writes.push(type_id);
+    }
+
+    /// Records that the resource `T` should be constructed with
+    /// [`FromWorld`] if the world doesn't contain it before the system
+    /// first runs.
+    pub fn init<T: Resource + FromWorld>(&mut self) {
+        self.inits.push(|world| {
+            world.init_resource::<T>();
+        });
+    }
+
+    /// Constructs every resource recorded with [`init`](Self::init)
+    /// which the world doesn't contain yet.
+    pub fn init_missing(&self, world: &mut World) {
+        for init in self.inits.iter() {
+            init(world);
+        }
+    }
+
+    pub fn reads(&self) -> &[TypeId] {
+        &self.reads
+    }
+
+    pub fn writes(&self) -> &[TypeId] {
+        &self.writes
+    }
+
+    /// Returns whether any non-Send resource is borrowed, in which case
+    /// the system has to run on the thread which owns the world.
+    pub fn is_non_send(&self) -> bool {
+        !self.non_send_reads.is_empty() || !self.non_send_writes.is_empty()
+    }
+
+    /// Adds every borrow recorded in `other` to this access set without
+    /// checking them against each other, since the systems of a group run
+    /// one after another. A conflict within `other` is kept.
+    pub fn extend(&mut self, other: &Access) {
+        self.reads.extend_from_slice(&other.reads);
+        self.writes.extend_from_slice(&other.writes);
+        self.non_send_reads.extend_from_slice(&other.non_send_reads);
+        self.non_send_writes
+            .extend_from_slice(&other.non_send_writes);
+        self.inits.extend_from_slice(&other.inits);
+
+        if self.conflict.is_none() {
+            self.conflict = other.conflict;
+        }
+    }
+
+    /// Returns the name of the first type which was borrowed exclusively
+    /// alongside another borrow of the same type, if any.
+    pub fn conflict(&self) -> Option<&'static str> {
+        self.conflict
+    }
+
+    fn set_conflict<T: 'static>(&mut self) {
+        if self.conflict.is_none() {
+            self.conflict = Some(any::type_name::<T>());
+        }
+    }
+}
+
+/// The resources and components requested by a single system.
+#[derive(Clone, Debug, Default)]
+pub struct SystemAccess {
+    pub resources: Access,
+    pub components: Access,
+}
+
+impl SystemAccess {
+    pub fn of<'a, S: System<'a>>() -> Self {
+        let mut access = Self::default();
+        S::Resources::access(&mut access.resources);
+        S::Components::access(&mut access.components);
+        access
+    }
+
+    /// Checks that `system` never borrows the same resource or component
+    /// mutably alongside another borrow of it. A system which fails this
+    /// check would fail to fetch its data every time it runs.
+    pub fn validate(&self, system: &'static str) -> Result<(), AccessError> {
+        if let Some(resource) = self.resources.conflict() {
+            return Err(AccessError::ConflictingResourceAccess { system, resource });
+        }
+
+        if let Some(component) = self.components.conflict() {
+            return Err(AccessError::ConflictingComponentAccess { system, component });
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetches the data of a system or observer which last ran at
+/// `last_run`, and claims the change tick it was fetched at. If another
+/// system claims the tick while this one is fetching, the data is
+/// fetched again at the next tick. A failed fetch claims no tick.
+pub(crate) fn fetch<'a, R, C>(
+    world: &World,
+    resources: &'a ResourceStorageAllocator,
+    components: &'a ComponentStorageAllocator,
+    state: &'a mut R::State,
+    last_run: u64,
+) -> Result<(R, C, SystemTicks), RetrievalError>
+where
+    R: ResourceData<'a>,
+    C: ComponentData<'a>,
+{
+    // The borrow checker can't tell that the state is only returned
+    // borrowed from the last attempt, so it is reborrowed through a raw
+    // pointer.
+    let state: *mut R::State = state;
+
+    loop {
+        let ticks = SystemTicks::new(last_run, world.change_tick());
+
+        // SAFETY: the data fetched by a previous attempt, which borrowed
+        // the state, was dropped at the end of that attempt.
+        let fetched_resources = R::fetch(resources, unsafe { &mut *state }, ticks)?;
+        let fetched_components = C::fetch(components, ticks)?;
+
+        if world.claim_change_tick(ticks.this_run()) {
+            return Ok((fetched_resources, fetched_components, ticks));
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum AccessError {
+    ConflictingResourceAccess {
+        system: &'static str,
+        resource: &'static str,
+    },
+    ConflictingComponentAccess {
+        system: &'static str,
+        component: &'static str,
+    },
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AccessError::*;
+
+        match *self {
+            ConflictingResourceAccess { system, resource } => write!(
+                f,
+                "System {} borrows resource {} mutably alongside another borrow of it.",
+                system, resource,
+            ),
+            ConflictingComponentAccess { system, component } => write!(
+                f,
+                "System {} borrows component {} mutably alongside another borrow of it.",
+                system, component,
+            ),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 pub enum RetrievalError {
     ResourceLockedExclusive,
     ResourceLockedShared,
     ResourceStorageInUse,
     NoSuchResourceStorage,
+    NonSendOffThread,
     ComponentLockedExclusive,
     ComponentLockedShared,
     ComponentStorageInUse,
@@ -47,6 +299,9 @@ impl fmt::Display for RetrievalError {
             ResourceLockedShared => "The world resource allocator is currently locked (R).",
             ResourceStorageInUse => "The requested resource storage is currently in use.",
             NoSuchResourceStorage => "No storage has been registered for the requested resource.",
+            NonSendOffThread => {
+                "Non-Send resources can only be fetched on the thread which owns the world."
+            }
             ComponentLockedExclusive => "The world component allocator is currently locked (W).",
             ComponentLockedShared => "The world component allocator is currently locked (R).",
             ComponentStorageInUse => "The requested component storage is currently in use.",
@@ -61,43 +316,128 @@ mod impls {
     use crate::{
         component::Component,
         resource::Resource,
-        storage::{ReadComponent, ReadResource, WriteComponent, WriteResource},
+        storage::{
+            NonSend, NonSendMut, ReadComponent, ReadResource, WriteComponent, WriteResource,
+        },
     };
 
     use super::*;
 
     impl<'a, T: Resource> ResourceData<'a> for ReadResource<'a, T> {
-        fn fetch(allocator: &'a ResourceStorageAllocator) -> Result<Self, RetrievalError> {
+        type State = ();
+
+        fn init_state() {}
+
+        fn fetch(
+            allocator: &'a ResourceStorageAllocator,
+            _: &'a mut (),
+            ticks: SystemTicks,
+        ) -> Result<Self, RetrievalError> {
             if allocator.contains::<T>() {
                 let storage = unsafe {
                     allocator
                         .try_get_unchecked::<T>()
                         .ok_or(RetrievalError::ResourceStorageInUse)?
                 };
-                Ok(ReadResource::new(storage))
+                Ok(ReadResource::new(storage, ticks.last_run()))
             } else {
                 Err(RetrievalError::NoSuchResourceStorage)
             }
         }
+
+        fn access(access: &mut Access) {
+            access.read::<T>();
+        }
     }
 
     impl<'a, T: Resource> ResourceData<'a> for WriteResource<'a, T> {
-        fn fetch(allocator: &'a ResourceStorageAllocator) -> Result<Self, RetrievalError> {
+        type State = ();
+
+        fn init_state() {}
+
+        fn fetch(
+            allocator: &'a ResourceStorageAllocator,
+            _: &'a mut (),
+            ticks: SystemTicks,
+        ) -> Result<Self, RetrievalError> {
             if allocator.contains::<T>() {
                 let storage = unsafe {
                     allocator
                         .try_get_mut_unchecked::<T>()
                         .ok_or(RetrievalError::ResourceStorageInUse)?
                 };
-                Ok(WriteResource::new(storage))
+                Ok(WriteResource::new(storage, ticks))
+            } else {
+                Err(RetrievalError::NoSuchResourceStorage)
+            }
+        }
+
+        fn access(access: &mut Access) {
+            access.write::<T>();
+        }
+    }
+
+    impl<'a, T: 'static> ResourceData<'a> for NonSend<'a, T> {
+        type State = ();
+
+        fn init_state() {}
+
+        fn fetch(
+            allocator: &'a ResourceStorageAllocator,
+            _: &'a mut (),
+            _: SystemTicks,
+        ) -> Result<Self, RetrievalError> {
+            let storage = allocator.non_send();
+
+            if !storage.is_owner() {
+                Err(RetrievalError::NonSendOffThread)
+            } else if storage.contains::<T>() {
+                storage
+                    .try_get::<T>()
+                    .ok_or(RetrievalError::ResourceStorageInUse)
+            } else {
+                Err(RetrievalError::NoSuchResourceStorage)
+            }
+        }
+
+        fn access(access: &mut Access) {
+            access.read_non_send::<T>();
+        }
+    }
+
+    impl<'a, T: 'static> ResourceData<'a> for NonSendMut<'a, T> {
+        type State = ();
+
+        fn init_state() {}
+
+        fn fetch(
+            allocator: &'a ResourceStorageAllocator,
+            _: &'a mut (),
+            _: SystemTicks,
+        ) -> Result<Self, RetrievalError> {
+            let storage = allocator.non_send();
+
+            if !storage.is_owner() {
+                Err(RetrievalError::NonSendOffThread)
+            } else if storage.contains::<T>() {
+                storage
+                    .try_get_mut::<T>()
+                    .ok_or(RetrievalError::ResourceStorageInUse)
             } else {
                 Err(RetrievalError::NoSuchResourceStorage)
             }
         }
+
+        fn access(access: &mut Access) {
+            access.write_non_send::<T>();
+        }
     }
 
     impl<'a, T: Component> ComponentData<'a> for ReadComponent<'a, T> {
-        fn fetch(allocator: &'a ComponentStorageAllocator) -> Result<Self, RetrievalError> {
+        fn fetch(
+            allocator: &'a ComponentStorageAllocator,
+            _: SystemTicks,
+        ) -> Result<Self, RetrievalError> {
             if allocator.contains::<T>() {
                 let storage = unsafe {
                     allocator
@@ -109,33 +449,56 @@ mod impls {
                 Err(RetrievalError::NoSuchComponentStorage)
             }
         }
+
+        fn access(access: &mut Access) {
+            access.read::<T>();
+        }
     }
 
     impl<'a, T: Component> ComponentData<'a> for WriteComponent<'a, T> {
-        fn fetch(allocator: &'a ComponentStorageAllocator) -> Result<Self, RetrievalError> {
+        fn fetch(
+            allocator: &'a ComponentStorageAllocator,
+            ticks: SystemTicks,
+        ) -> Result<Self, RetrievalError> {
             if allocator.contains::<T>() {
                 let storage = unsafe {
                     allocator
                         .try_get_mut_unchecked::<T>()
                         .ok_or(RetrievalError::ComponentStorageInUse)?
                 };
-                Ok(WriteComponent::new(storage))
+                Ok(WriteComponent::new(storage, ticks.this_run()))
             } else {
                 Err(RetrievalError::NoSuchComponentStorage)
             }
         }
+
+        fn access(access: &mut Access) {
+            access.write::<T>();
+        }
     }
 
     impl ResourceData<'_> for () {
-        fn fetch(_: &ResourceStorageAllocator) -> Result<Self, RetrievalError> {
+        type State = ();
+
+        fn init_state() {}
+
+        fn fetch(
+            _: &ResourceStorageAllocator,
+            _: &mut (),
+            _: SystemTicks,
+        ) -> Result<Self, RetrievalError> {
             Ok(())
         }
+
+        fn access(_: &mut Access) {}
     }
 
     impl ComponentData<'_> for () {
-        fn fetch(_: &ComponentStorageAllocator) -> Result<Self, RetrievalError> {
+        fn fetch(_: &ComponentStorageAllocator, _: SystemTicks) -> Result<Self, RetrievalError> {
             Ok(())
         }
+
+        fn access(_: &mut Access) {}
     }
 
     macro_rules! impl_rd {
@@ -146,10 +509,27 @@ mod impls {
                     $t: ResourceData<'a>,
                 )+
             {
+                type State = ($(<$t as ResourceData<'a>>::State,)+);
+
+                fn init_state() -> Self::State {
+                    ($(<$t as ResourceData<'a>>::init_state(),)+)
+                }
+
                 fn fetch(
-                    allocator: &'a ResourceStorageAllocator
+                    allocator: &'a ResourceStorageAllocator,
+                    state: &'a mut Self::State,
+                    ticks: SystemTicks,
                 ) -> Result<Self, RetrievalError> {
-                    Ok(($(<$t as ResourceData<'_>>::fetch(allocator)?),*,))
+                    #[allow(non_snake_case)]
+                    let ($($t,)+) = state;
+
+                    Ok(($(<$t as ResourceData<'_>>::fetch(allocator, $t, ticks)?),*,))
+                }
+
+                fn access(access: &mut Access) {
+                    $(
+                        <$t as ResourceData<'_>>::access(access);
+                    )+
                 }
             }
         }
@@ -164,9 +544,16 @@ mod impls {
                 )+
             {
                 fn fetch(
-                    allocator: &'a ComponentStorageAllocator
+                    allocator: &'a ComponentStorageAllocator,
+                    ticks: SystemTicks,
                 ) -> Result<Self, RetrievalError> {
-                    Ok(($(<$t as ComponentData<'_>>::fetch(allocator)?),*,))
+                    Ok(($(<$t as ComponentData<'_>>::fetch(allocator, ticks)?),*,))
+                }
+
+                fn access(access: &mut Access) {
+                    $(
+                        <$t as ComponentData<'_>>::access(access);
+                    )+
                 }
             }
         }
@@ -0,0 +1,192 @@
+use std::{
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{storage::WriteResource, system::System};
+
+/// The resource maintained by the dispatcher at the start of every tick.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Time {
+    delta: Duration,
+    elapsed: Duration,
+    unscaled_delta: Duration,
+    unscaled_elapsed: Duration,
+    tick: u64,
+    scale: f64,
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self {
+            delta: Duration::from_secs(0),
+            elapsed: Duration::from_secs(0),
+            unscaled_delta: Duration::from_secs(0),
+            unscaled_elapsed: Duration::from_secs(0),
+            tick: 0,
+            scale: 1.0,
+        }
+    }
+
+    /// The scaled time between the start of the previous tick and the
+    /// start of the current one.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// The scaled time elapsed over every tick so far.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The time between the start of the previous tick and the start of
+    /// the current one, ignoring the time scale.
+    pub fn unscaled_delta(&self) -> Duration {
+        self.unscaled_delta
+    }
+
+    /// The time elapsed over every tick so far, ignoring the time scale.
+    pub fn unscaled_elapsed(&self) -> Duration {
+        self.unscaled_elapsed
+    }
+
+    /// The number of ticks which have started, including the current one.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Sets the factor applied to the clock when computing the scaled
+    /// delta. A scale of `0.0` pauses scaled time entirely.
+    pub fn set_scale(&mut self, scale: f64) {
+        assert!(
+            scale >= 0.0 && scale.is_finite(),
+            "Time scale must be finite and non-negative."
+        );
+        self.scale = scale;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.scale == 0.0
+    }
+
+    pub(crate) fn advance(&mut self, unscaled_delta: Duration) {
+        self.unscaled_delta = unscaled_delta;
+        self.unscaled_elapsed += unscaled_delta;
+        self.delta = unscaled_delta.mul_f64(self.scale);
+        self.elapsed += self.delta;
+        self.tick += 1;
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The source of time used by the dispatcher to update [`Time`].
+#[derive(Clone, Debug, Default)]
+pub enum Clock {
+    /// Measures the time which has actually passed.
+    #[default]
+    Real,
+    /// Only moves forward when the handle is advanced.
+    Manual(ManualClock),
+}
+
+/// A handle to a clock which only moves forward when it is advanced.
+/// Every clone of the handle refers to the same clock.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances the clock by `duration`. The clock saturates at
+    /// `u64::MAX` nanoseconds, rather than wrapping around.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+
+        self.nanos
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                Some(current.saturating_add(nanos))
+            })
+            .ok();
+    }
+
+    /// The total amount the clock has been advanced by.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Acquire))
+    }
+}
+
+/// Advances the [`Time`] resource. The dispatcher runs this before any
+/// other system at the start of every tick.
+#[derive(Debug)]
+pub(crate) struct TimeSystem {
+    clock: Clock,
+    start: Instant,
+    last: Duration,
+}
+
+impl TimeSystem {
+    pub(crate) fn new(clock: Clock) -> Self {
+        let start = Instant::now();
+        let last = match &clock {
+            Clock::Real => Duration::from_secs(0),
+            Clock::Manual(manual) => manual.elapsed(),
+        };
+
+        Self { clock, start, last }
+    }
+
+    fn now(&self) -> Duration {
+        match &self.clock {
+            Clock::Real => self.start.elapsed(),
+            Clock::Manual(manual) => manual.elapsed(),
+        }
+    }
+}
+
+impl<'a> System<'a> for TimeSystem {
+    type Resources = (WriteResource<'a, Time>,);
+    type Components = ();
+
+    fn execute(&mut self, (mut time,): Self::Resources, _: ()) {
+        let now = self.now();
+        let delta = now - self.last;
+        self.last = now;
+
+        time.advance(delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_saturates() {
+        let clock = ManualClock::new();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+
+        clock.advance(Duration::MAX);
+        assert_eq!(clock.elapsed(), Duration::from_nanos(u64::MAX));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_nanos(u64::MAX));
+    }
+}
@@ -0,0 +1,35 @@
+//! Fixtures shared by the tests of several modules.
+
+use std::{cell::Cell, ptr};
+
+thread_local! {
+    static DROPS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A value which counts how many times it has been dropped. `Counted`
+/// without a value is zero-sized.
+///
+/// Drops are counted per thread. Every test runs on its own thread, so
+/// tests running in parallel don't affect each other's count.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Counted<T = ()>(pub(crate) T);
+
+impl<T> Drop for Counted<T> {
+    fn drop(&mut self) {
+        DROPS.with(|drops| drops.set(drops.get() + 1));
+    }
+}
+
+/// The number of [`Counted`] values dropped on the current thread.
+pub(crate) fn drops() -> usize {
+    DROPS.with(Cell::get)
+}
+
+/// Drops the `T` at `ptr`, as the drop function of a dynamic component.
+///
+/// # Safety
+///
+/// `ptr` must point to a valid `T`, which must not be used afterwards.
+pub(crate) unsafe fn drop_as<T>(ptr: *mut u8) {
+    ptr::drop_in_place(ptr as *mut T);
+}
@@ -9,12 +9,16 @@ use std::hint::unreachable_unchecked;
 /// optimizations. Only call this when you are able to ensure that the branch containing
 /// a call to this function code can never as calling it in release mode is undefined
 /// behavior.
+///
+/// # Safety
+///
+/// This function must never be reached in release mode.
 #[cfg(debug_assertions)]
 pub unsafe fn debug_unreachable(msg: &'static str) -> ! {
     if msg.trim() == "" {
         unreachable!();
     } else {
-        unreachable!(msg)
+        unreachable!("{}", msg)
     }
 }
 
@@ -23,6 +27,10 @@ pub unsafe fn debug_unreachable(msg: &'static str) -> ! {
 /// optimizations. Only call this when you are able to ensure that the branch containing
 /// a call to this function code can never as calling it in release mode is undefined
 /// behavior.
+///
+/// # Safety
+///
+/// This function must never be reached in release mode.
 #[cfg(not(debug_assertions))]
 pub unsafe fn debug_unreachable(_: &'static str) -> ! {
     unreachable_unchecked();
@@ -33,6 +41,10 @@ pub unsafe fn debug_unreachable(_: &'static str) -> ! {
 /// optimizations. Only call this when you are able to ensure that the branch containing
 /// a call to this function code can never as calling it in release mode is undefined
 /// behavior.
+///
+/// # Safety
+///
+/// This function must never be reached in release mode.
 #[cfg(debug_assertions)]
 pub unsafe fn debug_closure<F: FnOnce()>(f: F) -> ! {
     f();
@@ -44,6 +56,10 @@ pub unsafe fn debug_closure<F: FnOnce()>(f: F) -> ! {
 /// optimizations. Only call this when you are able to ensure that the branch containing
 /// a call to this function code can never as calling it in release mode is undefined
 /// behavior.
+///
+/// # Safety
+///
+/// This function must never be reached in release mode.
 #[cfg(not(debug_assertions))]
 pub unsafe fn debug_closure<F: FnOnce()>(_: F) -> ! {
     unreachable_unchecked();
@@ -0,0 +1,266 @@
+use std::slice;
+
+use crate::{
+    archetype::Archetype,
+    component::{ComponentId, ComponentSet},
+    entity::Entity,
+    storage::ErasedParts,
+    world::World,
+};
+
+/// How a component is accessed by a [`DynamicQuery`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A query whose components are chosen at runtime by [`ComponentId`],
+/// rather than by Rust types. This works with both typed components and
+/// dynamic components registered with
+/// [`World::register_dynamic`](crate::World::register_dynamic).
+///
+/// Entities are matched by archetype, so a query only visits entities
+/// which have every component it reads, writes or filters `with`, and
+/// none of the components it filters `without`.
+#[derive(Clone, Default, Debug)]
+pub struct DynamicQuery {
+    fetches: Vec<(ComponentId, Access)>,
+    with: ComponentSet,
+    without: ComponentSet,
+}
+
+impl DynamicQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fetches a pointer to the component, which must only be read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the component is already fetched by the query.
+    pub fn read(self, component: ComponentId) -> Self {
+        self.fetch(component, Access::Read)
+    }
+
+    /// Fetches a pointer to the component, which may be written to. The
+    /// component is marked as changed for every entity visited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the component is already fetched by the query.
+    pub fn write(self, component: ComponentId) -> Self {
+        self.fetch(component, Access::Write)
+    }
+
+    /// Only matches entities which have the component, without fetching
+    /// it.
+    pub fn with(mut self, component: ComponentId) -> Self {
+        self.with.insert(component);
+        self
+    }
+
+    /// Only matches entities which don't have the component.
+    pub fn without(mut self, component: ComponentId) -> Self {
+        self.without.insert(component);
+        self
+    }
+
+    /// The components fetched by the query, in the order their pointers
+    /// are yielded.
+    pub fn fetches(&self) -> &[(ComponentId, Access)] {
+        &self.fetches
+    }
+
+    /// Returns whether entities of the archetype are visited by the
+    /// query.
+    pub fn matches(&self, archetype: &Archetype) -> bool {
+        let components = archetype.components();
+
+        components.is_superset(&self.with) && components.is_disjoint(&self.without)
+    }
+
+    /// Iterates every matching entity along with a pointer to each of
+    /// the fetched components, in the order they were added to the
+    /// query.
+    ///
+    /// The pointers stay valid while the world is borrowed by the
+    /// iterator. Components fetched with [`read`](Self::read) must not be
+    /// written through their pointers.
+    pub fn iter<'w>(&self, world: &'w mut World) -> DynamicQueryIter<'w> {
+        let change_tick = *world.change_tick.get_mut();
+        let components: Vec<_> = self
+            .fetches
+            .iter()
+            .map(|&(component, _)| component)
+            .collect();
+
+        // Every storage is split once, up front, so the pointers yielded
+        // for one entity stay valid while later entities are visited.
+        let (storages, archetypes) =
+            match world.component_storage.get_mut().erased_parts(&components) {
+                Some(parts) => {
+                    let writes = self
+                        .fetches
+                        .iter()
+                        .map(|&(_, access)| (access == Access::Write).then_some(change_tick));
+
+                    (
+                        parts.into_iter().zip(writes).collect(),
+                        world.archetypes.iter(),
+                    )
+                }
+                // A component without a storage was never added to any
+                // entity, so no entity can match.
+                None => (Vec::new(), [].iter()),
+            };
+
+        DynamicQueryIter {
+            query: self.clone(),
+            storages,
+            archetypes,
+            entities: [].iter(),
+        }
+    }
+
+    fn fetch(mut self, component: ComponentId, access: Access) -> Self {
+        if self.fetches.iter().any(|&(other, _)| other == component) {
+            panic!("Component {} is already fetched by the query.", component);
+        }
+
+        self.fetches.push((component, access));
+        self.with.insert(component);
+        self
+    }
+}
+
+/// Iterates the entities matched by a [`DynamicQuery`]. See
+/// [`DynamicQuery::iter`].
+#[derive(Debug)]
+pub struct DynamicQueryIter<'w> {
+    query: DynamicQuery,
+    storages: Vec<(ErasedParts<'w>, Option<u64>)>,
+    archetypes: slice::Iter<'w, Archetype>,
+    entities: slice::Iter<'w, Entity>,
+}
+
+impl Iterator for DynamicQueryIter<'_> {
+    type Item = (Entity, Vec<*mut u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entity = match self.entities.next() {
+                Some(&entity) => entity,
+                None => {
+                    let query = &self.query;
+                    let archetype = self.archetypes.find(|arch| query.matches(arch))?;
+                    self.entities = archetype.entities().iter();
+                    continue;
+                }
+            };
+
+            let ptrs = self
+                .storages
+                .iter()
+                // SAFETY: the world is borrowed mutably by the iterator, so
+                // the storages are alive and not borrowed elsewhere. Every
+                // entity is visited once, so the ticks of a component are
+                // only ever borrowed here.
+                .map(|(storage, write)| unsafe { storage.get(entity.id(), *write) })
+                .collect::<Option<Vec<_>>>();
+
+            if let Some(ptrs) = ptrs {
+                return Some((entity, ptrs));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Frozen;
+
+    fn world_with_speed() -> (World, ComponentId, Vec<Entity>) {
+        let mut world = World::new();
+        let speed = world.register_dynamic("speed", Layout::new::<u32>(), None);
+
+        let entities: Vec<_> = (0..10).map(|i| world.create_entity(Position(i))).collect();
+        for &entity in &entities {
+            let bytes = (entity.id() as u32 + 1).to_ne_bytes();
+            assert!(unsafe { world.insert_dynamic(entity, speed, &bytes) });
+        }
+        world.add_components(entities[3], Frozen).unwrap();
+
+        (world, speed, entities)
+    }
+
+    #[test]
+    fn collect_then_write() {
+        let (mut world, speed, entities) = world_with_speed();
+        let position = world.component_id::<Position>().unwrap();
+        let frozen = world.component_id::<Frozen>().unwrap();
+        let change_tick = world.increment_change_tick() + 1;
+
+        // Every pointer is collected before any of them is written, so
+        // the pointers yielded for earlier entities must stay valid.
+        let query = DynamicQuery::new()
+            .write(position)
+            .read(speed)
+            .without(frozen);
+        let items: Vec<_> = query.iter(&mut world).collect();
+        assert_eq!(items.len(), 9);
+
+        for (_, ptrs) in &items {
+            unsafe {
+                let speed = *(ptrs[1] as *const u32);
+                (*(ptrs[0] as *mut Position)).0 += speed;
+            }
+        }
+        drop(items);
+
+        let storage = world.component_allocator().get::<Position>().unwrap();
+        for entity in entities {
+            let id = entity.id();
+            let expected = if id == 3 { 3 } else { id as u32 * 2 + 1 };
+            assert_eq!(storage.get(id), Some(&Position(expected)));
+
+            let changed = storage.ticks_of(id).unwrap().changed();
+            assert_eq!(changed == change_tick, id != 3);
+        }
+    }
+
+    #[test]
+    fn write_dynamic_and_tags() {
+        let (mut world, speed, entities) = world_with_speed();
+        let frozen = world.component_id::<Frozen>().unwrap();
+
+        let query = DynamicQuery::new().write(speed).write(frozen);
+        let items: Vec<_> = query.iter(&mut world).collect();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].0, entities[3]);
+
+        unsafe { *(items[0].1[0] as *mut u32) = 40 };
+        drop(items);
+
+        let bytes = world.get_dynamic(entities[3], speed).unwrap();
+        assert_eq!(*bytes, 40u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn missing_storage_matches_nothing() {
+        let mut world = World::new();
+        world.create_entity(Position(0));
+        let unused = world.register_dynamic("unused", Layout::new::<u8>(), None);
+
+        assert_eq!(DynamicQuery::new().read(unused).iter(&mut world).count(), 0);
+    }
+}
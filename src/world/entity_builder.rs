@@ -0,0 +1,257 @@
+use std::{
+    alloc::{self, Layout},
+    fmt,
+    ptr::{self, NonNull},
+};
+
+use crate::{
+    component::{BoxedComponent, Component, ComponentId, ComponentSet, DropFn},
+    entity::Entity,
+    world::World,
+};
+
+/// A component waiting to be stored by an [`EntityBuilder`].
+enum Pending {
+    Boxed(BoxedComponent),
+    Dynamic(DynamicValue),
+}
+
+/// A dynamic component copied into memory aligned for its layout. The
+/// component is dropped with its drop function along with the value,
+/// unless it was moved out with [`forget`](Self::forget).
+struct DynamicValue {
+    data: NonNull<u8>,
+    layout: Layout,
+    drop: Option<DropFn>,
+}
+
+impl DynamicValue {
+    /// # Safety
+    ///
+    /// `bytes` must be a valid component of the given layout, which can
+    /// be dropped with `drop`.
+    unsafe fn new(bytes: &[u8], layout: Layout, drop: Option<DropFn>) -> Self {
+        let data = if layout.size() == 0 {
+            // SAFETY: the alignment of a layout is never zero.
+            NonNull::new_unchecked(ptr::without_provenance_mut(layout.align()))
+        } else {
+            let data = alloc::alloc(layout);
+            NonNull::new(data).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+        };
+
+        ptr::copy_nonoverlapping(bytes.as_ptr(), data.as_ptr(), layout.size());
+
+        Self { data, layout, drop }
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        self.data.as_ptr()
+    }
+
+    /// Frees the memory of the value without dropping the component,
+    /// once the component was moved elsewhere.
+    fn forget(mut self) {
+        self.drop = None;
+    }
+}
+
+impl Drop for DynamicValue {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            // SAFETY: the value holds a valid component, which wasn't
+            // moved out, since `forget` clears the drop function.
+            unsafe { drop(self.data.as_ptr()) };
+        }
+
+        if self.layout.size() != 0 {
+            // SAFETY: the memory was allocated with this layout.
+            unsafe { alloc::dealloc(self.data.as_ptr(), self.layout) };
+        }
+    }
+}
+
+/// Builds an entity out of components added one at a time, returned by
+/// [`World::spawn`]. Unlike [`World::create_entity`], the components
+/// don't need to be known as a single tuple type.
+///
+/// Nothing is stored until [`build`](Self::build) is called, which places
+/// the entity directly in the archetype of all of its components, rather
+/// than moving it once for every component.
+pub struct EntityBuilder<'w> {
+    world: &'w mut World,
+    components: Vec<(ComponentId, Pending)>,
+}
+
+impl<'w> EntityBuilder<'w> {
+    pub(crate) fn new(world: &'w mut World) -> Self {
+        Self {
+            world,
+            components: Vec::new(),
+        }
+    }
+
+    /// Adds the component to the entity. If the entity already has a
+    /// component of the same type, it is replaced.
+    pub fn with<T: Component>(self, component: T) -> Self {
+        self.with_boxed(BoxedComponent::new(component))
+    }
+
+    /// Adds the component to the entity if `condition` is true.
+    pub fn with_if<T: Component>(self, condition: bool, component: T) -> Self {
+        if condition {
+            self.with(component)
+        } else {
+            self
+        }
+    }
+
+    /// Adds a type-erased component to the entity. If the entity already
+    /// has a component of the same type, it is replaced.
+    pub fn with_boxed(mut self, component: BoxedComponent) -> Self {
+        let id = component.init(self.world.component_storage.get_mut().registry_mut());
+        self.push(id, Pending::Boxed(component));
+        self
+    }
+
+    /// Copies a dynamic component out of `bytes` and adds it to the
+    /// entity. If the entity already has the component, it is replaced,
+    /// and the replaced value is dropped with the drop function of the
+    /// component. The same happens if the builder is dropped without
+    /// being built.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `component` isn't a dynamic component, or `bytes` isn't
+    /// as long as its layout.
+    ///
+    /// # Safety
+    ///
+    /// See [`World::insert_dynamic`].
+    pub unsafe fn with_dynamic(mut self, component: ComponentId, bytes: &[u8]) -> Self {
+        let allocator = self.world.component_storage.get_mut();
+        let (layout, drop) = match allocator.get_dynamic(component) {
+            Some(storage) => (storage.layout(), storage.drop_fn()),
+            None => panic!("Component {} is not a dynamic component.", component),
+        };
+        assert_eq!(
+            bytes.len(),
+            layout.size(),
+            "The bytes of dynamic component {} don't match its layout.",
+            component,
+        );
+
+        let value = DynamicValue::new(bytes, layout, drop);
+        self.push(component, Pending::Dynamic(value));
+        self
+    }
+
+    /// Creates the entity with every component added so far, along with
+    /// the components they require.
+    pub fn build(self) -> Entity {
+        let Self { world, components } = self;
+
+        let mut comp_set: ComponentSet = components.iter().map(|&(id, _)| id).collect();
+        let required = world.required.complete(&mut comp_set);
+        let entity = Entity::new(world.next_id);
+
+        world.create_entity_impl(
+            entity,
+            &comp_set,
+            &required,
+            |entity, archetype, allocator, tick| {
+                for (id, component) in components {
+                    match component {
+                        Pending::Boxed(component) => {
+                            component.store(entity, archetype, allocator, tick)
+                        }
+                        Pending::Dynamic(value) => {
+                            if let Some(mut storage) = allocator.get_dynamic_mut(id) {
+                                storage.set_change_tick(tick);
+                                // SAFETY: the caller of `with_dynamic` guaranteed
+                                // the value is a valid component, and the entity
+                                // is new, so the value is always taken.
+                                unsafe { storage.push(entity.id(), value.as_ptr()) };
+                                value.forget();
+                            }
+                        }
+                    }
+                }
+            },
+        );
+        world.apply_commands();
+
+        entity
+    }
+
+    fn push(&mut self, id: ComponentId, component: Pending) {
+        match self.components.iter_mut().find(|(other, _)| *other == id) {
+            Some((_, pending)) => *pending = component,
+            None => self.components.push((id, component)),
+        }
+    }
+}
+
+impl fmt::Debug for EntityBuilder<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let components: Vec<_> = self.components.iter().map(|&(id, _)| id).collect();
+
+        f.debug_struct("EntityBuilder")
+            .field("components", &components)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        mem::{self, ManuallyDrop},
+        slice,
+    };
+
+    use crate::test_utils::{drop_as, drops, Counted};
+
+    use super::*;
+
+    type Name = Counted<String>;
+
+    /// Adds `value` to the builder as the dynamic component `id`.
+    fn with_counted<'w>(
+        builder: EntityBuilder<'w>,
+        id: ComponentId,
+        value: &str,
+    ) -> EntityBuilder<'w> {
+        let value = ManuallyDrop::new(Counted(value.to_string()));
+        let bytes = unsafe {
+            slice::from_raw_parts(&*value as *const Name as *const u8, mem::size_of::<Name>())
+        };
+
+        unsafe { builder.with_dynamic(id, bytes) }
+    }
+
+    #[test]
+    fn drops_pending_dynamic_values() {
+        let mut world = World::new();
+        let id = world.register_dynamic("Counted", Layout::new::<Name>(), Some(drop_as::<Name>));
+
+        // The replaced value is dropped right away, and the value left
+        // over when the builder is dropped unbuilt with it.
+        let builder = with_counted(world.spawn(), id, "a");
+        let builder = with_counted(builder, id, "b");
+        assert_eq!(drops(), 1);
+        drop(builder);
+        assert_eq!(drops(), 2);
+
+        // A built value belongs to the world from then on.
+        let builder = with_counted(world.spawn(), id, "c");
+        let entity = with_counted(builder, id, "d").build();
+        assert_eq!(drops(), 3);
+
+        let bytes = world.get_dynamic(entity, id).unwrap();
+        let value = unsafe { &*(bytes.as_ptr() as *const Name) };
+        assert_eq!(value.0, "d");
+        drop(bytes);
+
+        assert!(world.remove_dynamic(entity, id));
+        assert_eq!(drops(), 4);
+    }
+}
@@ -1,42 +1,95 @@
+use std::{
+    alloc::Layout,
+    any::{self, TypeId},
+    borrow::Cow,
+    collections::HashMap,
+    mem::ManuallyDrop,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
 use parking_lot::RwLock;
 
 use crate::{
-    archetype::Archetype,
-    component::{ComponentSet, ComponentTuple, IntoComponentTuple},
+    archetype::{AddEdge, Archetype, ArchetypeId, Bundle},
+    cell::AtomicRef,
+    change::Mut,
+    component::{
+        Component, ComponentId, ComponentInfo, ComponentRegistry, ComponentSet, ComponentTuple,
+        DropFn, IntoComponentTuple, RequiredComponent, RequiredComponents,
+    },
     entity::{Entity, EntityId},
-    storage::{ComponentStorageAllocator, ResourceStorageAllocator},
+    hierarchy::Parent,
+    observer::{Observer, Observers},
+    resource::Resource,
+    storage::{
+        ComponentStorage, ComponentStorageAllocator, NonSend, ReadResource, ResourceStorage,
+        ResourceStorageAllocator,
+    },
+    system::{AccessError, RetrievalError},
     utils, IntoResourceTuple, ResourceTuple,
 };
 
+pub mod dynamic_query;
+pub mod entity_builder;
 pub mod query;
 
+use entity_builder::EntityBuilder;
+
+/// Constructs a value using the contents of a [`World`]. This is used by
+/// [`World::init_resource`], and is implemented for every type which
+/// implements `Default`.
+pub trait FromWorld {
+    fn from_world(world: &mut World) -> Self;
+}
+
+impl<T: Default> FromWorld for T {
+    fn from_world(_: &mut World) -> Self {
+        T::default()
+    }
+}
+
 #[derive(Debug)]
 pub struct World {
     archetypes: Vec<Archetype>,
+    archetype_ids: HashMap<ComponentSet, ArchetypeId>,
+    locations: HashMap<EntityId, ArchetypeId>,
     entities: Vec<Entity>,
     resource_storage: RwLock<ResourceStorageAllocator>,
     component_storage: RwLock<ComponentStorageAllocator>,
     next_id: EntityId,
+    change_tick: AtomicU64,
+    observers: Observers,
+    required: RequiredComponents,
 }
 
 impl World {
     pub fn new() -> Self {
         Self {
             archetypes: Vec::new(),
+            archetype_ids: HashMap::new(),
+            locations: HashMap::new(),
             entities: Vec::new(),
             resource_storage: RwLock::new(ResourceStorageAllocator::new()),
             component_storage: RwLock::new(ComponentStorageAllocator::new()),
             next_id: 0,
+            change_tick: AtomicU64::new(1),
+            observers: Observers::new(),
+            required: RequiredComponents::new(),
         }
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             archetypes: Vec::with_capacity(capacity),
+            archetype_ids: HashMap::with_capacity(capacity),
+            locations: HashMap::with_capacity(capacity),
             entities: Vec::with_capacity(capacity),
             resource_storage: RwLock::new(ResourceStorageAllocator::new()),
             component_storage: RwLock::new(ComponentStorageAllocator::new()),
             next_id: 0,
+            change_tick: AtomicU64::new(1),
+            observers: Observers::new(),
+            required: RequiredComponents::new(),
         }
     }
 
@@ -48,14 +101,25 @@ impl World {
     {
         let iter = container.into_iter();
         let start_index = self.entities.len();
-        let comp_set = ComponentSet::from_tuple::<CT>();
+        let mut comp_set =
+            ComponentSet::from_tuple::<CT>(self.component_storage.get_mut().registry_mut());
+        let required = self.required.complete(&mut comp_set);
 
         for into_ct in iter {
-            let components = into_ct.into();
+            let components: CT = into_ct.into();
             let entity = Entity::new(self.next_id);
-            self.create_entity_impl(entity, components, &comp_set);
+            self.create_entity_impl(
+                entity,
+                &comp_set,
+                &required,
+                |entity, archetype, allocator, tick| {
+                    components.store(entity, archetype, allocator, tick)
+                },
+            );
         }
 
+        self.apply_commands();
+
         &self.entities[start_index..]
     }
 
@@ -64,11 +128,30 @@ impl World {
         ICT: IntoComponentTuple<CT>,
         CT: ComponentTuple,
     {
-        let comp_set = ComponentSet::from_tuple::<CT>();
-        let components = components.into();
+        let mut comp_set =
+            ComponentSet::from_tuple::<CT>(self.component_storage.get_mut().registry_mut());
+        let required = self.required.complete(&mut comp_set);
+        let components: CT = components.into();
         let entity = Entity::new(self.next_id);
 
-        self.create_entity_impl(entity, components, &comp_set)
+        self.create_entity_impl(
+            entity,
+            &comp_set,
+            &required,
+            |entity, archetype, allocator, tick| {
+                components.store(entity, archetype, allocator, tick)
+            },
+        );
+        self.apply_commands();
+
+        entity
+    }
+
+    /// Starts building an entity whose components are added one at a
+    /// time. The entity is only created once
+    /// [`build`](EntityBuilder::build) is called.
+    pub fn spawn(&mut self) -> EntityBuilder<'_> {
+        EntityBuilder::new(self)
     }
 
     pub fn add_components<ICT, CT>(&mut self, entity: Entity, components: ICT) -> Result<(), CT>
@@ -76,24 +159,108 @@ impl World {
         ICT: IntoComponentTuple<CT>,
         CT: ComponentTuple,
     {
-        let new_comp_set = ComponentSet::from_tuple::<CT>();
         let components = components.into();
-        self.add_components_impl(entity, components, new_comp_set)
+        self.add_components_impl(entity, components)?;
+        self.apply_commands();
+
+        Ok(())
+    }
+
+    /// Registers the storage for components of type `T` using the
+    /// storage returned by `f`, which can carry [`ComponentHooks`].
+    /// Returns `false` if the storage was already registered, in which
+    /// case `f` is not called.
+    ///
+    /// [`ComponentHooks`]: crate::hook::ComponentHooks
+    pub fn register_component_with<T, F>(&mut self, f: F) -> bool
+    where
+        T: Component,
+        F: FnOnce() -> ComponentStorage<T>,
+    {
+        self.component_storage.get_mut().register_with(f)
+    }
+
+    /// Makes components of type `A` require a component of type `B`. When
+    /// an entity is created with an `A`, or an `A` is added to it, a
+    /// default `B` is inserted alongside it unless the entity already has
+    /// one. Requirements are followed transitively. Entities which already
+    /// contain an `A` are left as they are.
+    ///
+    /// Returns `false` if `A` already required `B`.
+    pub fn register_required<A, B>(&mut self) -> bool
+    where
+        A: Component,
+        B: Component + Default,
+    {
+        self.register_required_with::<A, B, _>(B::default)
+    }
+
+    /// Makes components of type `A` require a component of type `B`,
+    /// constructed with `f`. See [`World::register_required`].
+    pub fn register_required_with<A, B, F>(&mut self, f: F) -> bool
+    where
+        A: Component,
+        B: Component,
+        F: Fn() -> B + Send + Sync + 'static,
+    {
+        if !self
+            .required
+            .register::<A, B, F>(self.component_storage.get_mut().registry_mut(), f)
+        {
+            return false;
+        }
+
+        for archetype in &mut self.archetypes {
+            archetype.clear_add_edges();
+        }
+
+        true
     }
 
-    fn create_entity_impl<CT: ComponentTuple>(
+    /// Runs the commands pushed by component hooks, including any
+    /// commands pushed while doing so. This is done automatically after
+    /// components are stored through the world, and by the dispatcher
+    /// whenever it has exclusive access to the world.
+    pub fn apply_commands(&mut self) {
+        loop {
+            let commands = self.component_storage.get_mut().take_commands();
+
+            if commands.is_empty() {
+                break;
+            }
+
+            commands.apply(self);
+        }
+    }
+
+    /// Places the entity in the archetype of `comp_set`, and stores its
+    /// components with `store`, followed by the required components.
+    fn create_entity_impl<F>(
         &mut self,
         entity: Entity,
-        components: CT,
         comp_set: &ComponentSet,
-    ) -> Entity {
-        let archetype = match self.get_archetype_mut(&comp_set) {
+        required: &[RequiredComponent],
+        store: F,
+    ) -> Entity
+    where
+        F: FnOnce(Entity, ArchetypeId, &mut ComponentStorageAllocator, u64),
+    {
+        let archetype = match self.get_archetype_mut(comp_set) {
             Some(arch) => arch,
-            None => self.create_archetype::<CT>(comp_set.clone()),
+            None => self.create_archetype(comp_set.clone()),
         };
         archetype.push(entity);
+        let archetype = archetype.id();
+        self.locations.insert(entity.id(), archetype);
+
+        let allocator = self.component_storage.get_mut();
+        let change_tick = *self.change_tick.get_mut();
+        store(entity, archetype, allocator, change_tick);
+
+        for component in required {
+            component.store(entity, archetype, allocator, change_tick);
+        }
 
-        components.store(entity, self.component_storage.get_mut());
         self.entities.push(entity);
         self.next_id += 1;
 
@@ -104,30 +271,270 @@ impl World {
         &mut self,
         entity: Entity,
         components: CT,
-        new_comp_set: ComponentSet,
     ) -> Result<(), CT> {
-        let arch = match self.archetype_of_mut(entity) {
-            Some(arch) => arch,
+        let source = match self.locations.get(&entity.id()) {
+            Some(&source) => source,
             None => return Err(components),
         };
-        let old_comp_set = arch.components().clone();
 
-        arch.remove(entity);
+        let edge = self.add_edge(source, Bundle::Tuple(TypeId::of::<CT>()), |registry| {
+            ComponentSet::from_tuple::<CT>(registry)
+        });
+        let archetype = edge.target;
+
+        self.move_archetype(entity, source, archetype);
+
+        let allocator = self.component_storage.get_mut();
+        let change_tick = *self.change_tick.get_mut();
+        allocator.move_entity(entity, self.archetypes[source].components(), archetype);
+        components.store(entity, archetype, allocator, change_tick);
+
+        for component in &edge.required {
+            component.store(entity, archetype, allocator, change_tick);
+        }
+
+        Ok(())
+    }
+
+    /// Finds the archetype reached by adding the bundle to the archetype
+    /// `source`, along with the required components which need to be
+    /// inserted. The components of the bundle are only computed with
+    /// `set` if the edge wasn't cached yet.
+    fn add_edge<F>(&mut self, source: ArchetypeId, bundle: Bundle, set: F) -> AddEdge
+    where
+        F: FnOnce(&mut ComponentRegistry) -> ComponentSet,
+    {
+        if let Some(edge) = self.archetypes[source].add_edge(bundle) {
+            return edge.clone();
+        }
+
+        let new_comp_set = set(self.component_storage.get_mut().registry_mut());
+        let mut comp_set = self.archetypes[source].components().clone();
+
+        // Only the components the entity didn't have yet bring in their
+        // requirements.
+        let mut added = ComponentSet::new();
+        for id in new_comp_set.iter().filter(|&id| !comp_set.contains(id)) {
+            added.insert(id);
+        }
+
+        comp_set.union_with(&added);
+        let required = self.required.complete_from(&mut comp_set, &added);
+        let target = self.archetype_id(comp_set);
+
+        let edge = AddEdge { target, required };
+        self.archetypes[source].set_add_edge(bundle, edge.clone());
+        edge
+    }
+
+    /// Finds the archetype reached by removing the component from the
+    /// archetype `source`. Returns `None` if the archetype doesn't contain
+    /// the component.
+    fn remove_edge(&mut self, source: ArchetypeId, component: ComponentId) -> Option<ArchetypeId> {
+        if let Some(target) = self.archetypes[source].remove_edge(component) {
+            return Some(target);
+        }
+
+        let components = self.archetypes[source].components();
+        if !components.contains(component) {
+            return None;
+        }
+
+        let mut comp_set = components.clone();
+        comp_set.remove(component);
+
+        let target = self.archetype_id(comp_set);
+        self.archetypes[source].set_remove_edge(component, target);
+        Some(target)
+    }
+
+    /// Removes the component of type `T` from the entity and returns it,
+    /// moving the entity to the archetype without it. Returns `None` if
+    /// the entity has no such component.
+    pub fn remove_component<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let component_id = self.component_storage.get_mut().registry().get::<T>()?;
+
+        let source = *self.locations.get(&entity.id())?;
+        let archetype = self.remove_edge(source, component_id)?;
+
+        self.move_archetype(entity, source, archetype);
+
+        let change_tick = *self.change_tick.get_mut();
+        let allocator = self.component_storage.get_mut();
+        let component = allocator.get_mut::<T>().and_then(|mut storage| {
+            storage.set_change_tick(change_tick);
+            storage.remove_by_id(entity.id())
+        });
+        allocator.move_entity(entity, self.archetypes[archetype].components(), archetype);
+        self.apply_commands();
+
+        component
+    }
+
+    /// Registers a component with no Rust type, which is stored as raw
+    /// bytes of the given layout and dropped with `drop`, if any. Every
+    /// call registers a new component, even if the name was already
+    /// used.
+    pub fn register_dynamic<N>(
+        &mut self,
+        name: N,
+        layout: Layout,
+        drop: Option<DropFn>,
+    ) -> ComponentId
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.component_storage
+            .get_mut()
+            .register_dynamic(name, layout, drop)
+    }
+
+    /// Copies a dynamic component out of `bytes` and adds it to the
+    /// entity, moving the entity to the archetype with it. Returns
+    /// `false` if the entity doesn't exist or already has the component,
+    /// in which case nothing is copied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `component` isn't a dynamic component, or `bytes` isn't
+    /// as long as its layout.
+    ///
+    /// # Safety
+    ///
+    /// `bytes` must be a valid value of the component, which can be sent
+    /// and shared between threads and dropped with the drop function it
+    /// was registered with. The world takes ownership of the value, so it
+    /// must not be dropped elsewhere unless this returns `false`.
+    pub unsafe fn insert_dynamic(
+        &mut self,
+        entity: Entity,
+        component: ComponentId,
+        bytes: &[u8],
+    ) -> bool {
+        let size = match self.component_storage.get_mut().get_dynamic(component) {
+            Some(storage) => storage.layout().size(),
+            None => panic!("Component {} is not a dynamic component.", component),
+        };
+        assert_eq!(
+            bytes.len(),
+            size,
+            "The bytes of dynamic component {} don't match its layout.",
+            component,
+        );
+
+        let source = match self.locations.get(&entity.id()) {
+            Some(&source) => source,
+            None => return false,
+        };
+
+        if self.archetypes[source].components().contains(component) {
+            return false;
+        }
+
+        let edge = self.add_edge(source, Bundle::Component(component), |_| {
+            std::iter::once(component).collect()
+        });
+        let archetype = edge.target;
+
+        self.move_archetype(entity, source, archetype);
 
-        let comp_set = {
-            let old = old_comp_set.into_inner().into_iter();
-            let new = new_comp_set.into_inner().into_iter();
-            ComponentSet::new(old.chain(new).collect())
+        let allocator = self.component_storage.get_mut();
+        let change_tick = *self.change_tick.get_mut();
+        allocator.move_entity(entity, self.archetypes[source].components(), archetype);
+
+        if let Some(mut storage) = allocator.get_dynamic_mut(component) {
+            storage.set_change_tick(change_tick);
+            storage.push(entity.id(), bytes.as_ptr());
+        }
+
+        for required in &edge.required {
+            required.store(entity, archetype, allocator, change_tick);
+        }
+
+        self.apply_commands();
+        true
+    }
+
+    /// Removes and drops the dynamic component of the entity, moving the
+    /// entity to the archetype without it. Returns `false` if the entity
+    /// has no such component.
+    pub fn remove_dynamic(&mut self, entity: Entity, component: ComponentId) -> bool {
+        let source = match self.locations.get(&entity.id()) {
+            Some(&source) => source,
+            None => return false,
         };
 
-        let archetype = match self.get_archetype_mut(&comp_set) {
-            Some(arch) => arch,
-            None => self.create_archetype::<CT>(comp_set),
+        let archetype = match self.remove_edge(source, component) {
+            Some(archetype) => archetype,
+            None => return false,
         };
-        archetype.push(entity);
-        components.store(entity, self.component_storage.get_mut());
 
-        Ok(())
+        self.move_archetype(entity, source, archetype);
+
+        let change_tick = *self.change_tick.get_mut();
+        let allocator = self.component_storage.get_mut();
+        if let Some(mut storage) = allocator.get_dynamic_mut(component) {
+            storage.set_change_tick(change_tick);
+            storage.remove(entity.id());
+        }
+        allocator.move_entity(entity, self.archetypes[archetype].components(), archetype);
+        self.apply_commands();
+
+        true
+    }
+
+    /// Borrows the bytes of the dynamic component of the entity.
+    pub fn get_dynamic(
+        &self,
+        entity: Entity,
+        component: ComponentId,
+    ) -> Option<AtomicRef<'_, [u8]>> {
+        let storage = self.component_allocator().get_dynamic(component)?;
+
+        if !storage.contains(entity.id()) {
+            return None;
+        }
+
+        Some(AtomicRef::map(storage, |storage| {
+            storage.get(entity.id()).unwrap_or_else(|| unsafe {
+                utils::debug_unreachable("Dynamic component vanished after it was found.")
+            })
+        }))
+    }
+
+    /// Returns a pointer to the dynamic component of the entity, marking
+    /// it as changed. The pointer is invalidated when the component is
+    /// removed, or another entity gains or loses it.
+    pub fn get_dynamic_ptr(&mut self, entity: Entity, component: ComponentId) -> Option<*mut u8> {
+        let change_tick = *self.change_tick.get_mut();
+        let allocator = self.component_storage.get_mut();
+        let mut storage = allocator.get_dynamic_mut(component)?;
+
+        storage.set_change_tick(change_tick);
+        storage.get_ptr(entity.id())
+    }
+
+    /// The current change tick of the world. Components added or changed
+    /// outside of systems are recorded at this tick.
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick.load(Ordering::Acquire)
+    }
+
+    /// Advances the change tick of the world, returning the tick before
+    /// it was advanced. This is called every time a query is made, and
+    /// the returned tick is the tick the query's changes are recorded at.
+    pub fn increment_change_tick(&self) -> u64 {
+        self.change_tick.fetch_add(1, Ordering::AcqRel)
+    }
+
+    /// Advances the change tick of the world past `tick`, if it is still
+    /// the current tick. Systems fetch their data at the current tick and
+    /// only claim it once everything was fetched, so a failed fetch
+    /// doesn't use up a tick. Returns whether the tick was claimed.
+    pub(crate) fn claim_change_tick(&self, tick: u64) -> bool {
+        self.change_tick
+            .compare_exchange(tick, tick + 1, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
     }
 
     pub fn add_resources<IRT, RT>(&mut self, resources: IRT)
@@ -136,17 +543,222 @@ impl World {
         RT: ResourceTuple,
     {
         let resources = resources.into();
-        resources.store(self.resource_storage.get_mut());
+        resources.store(self.resource_storage_mut());
+    }
+
+    /// Inserts a resource into the world, replacing and returning the
+    /// resource of the same type if there already was one. A replacing
+    /// resource counts as newly added.
+    pub fn insert_resource<T: Resource>(&mut self, resource: T) -> Option<T> {
+        self.resource_storage_mut().insert(resource)
+    }
+
+    /// Inserts a resource constructed with [`FromWorld`] if the world
+    /// doesn't already contain a resource of type `T`, then returns the
+    /// resource.
+    pub fn init_resource<T: Resource + FromWorld>(&mut self) -> Mut<'_, T> {
+        if !self.contains_resource::<T>() {
+            let resource = T::from_world(self);
+            self.insert_resource(resource);
+        }
+
+        self.get_resource_mut().unwrap_or_else(|| unsafe {
+            utils::debug_unreachable("Resource could not be retrieved after it was inserted.")
+        })
+    }
+
+    /// Removes the resource of type `T` from the world and returns it.
+    pub fn remove_resource<T: Resource>(&mut self) -> Option<T> {
+        self.resource_storage_mut().remove::<T>()
+    }
+
+    /// Takes the resource of type `T` out of the world and runs `f` with
+    /// both the world and the resource, then puts the resource back. This
+    /// allows the resource to be mutated alongside the rest of the world.
+    /// Like [`get_resource_mut`](Self::get_resource_mut), the resource is
+    /// only marked as changed once it is dereferenced mutably. The
+    /// resource is put back even if `f` panics, and the world doesn't
+    /// contain it until then.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the world contains no resource of type `T`, or if `f`
+    /// tries to access the resource through the world.
+    pub fn resource_scope<T: Resource, R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut World, Mut<'_, T>) -> R,
+    {
+        let storage = self
+            .resource_storage_mut()
+            .begin_scope::<T>()
+            .unwrap_or_else(|| {
+                panic!(
+                    "Resource {} does not exist in the world.",
+                    any::type_name::<T>(),
+                )
+            });
+
+        let mut guard = ScopeGuard {
+            world: self,
+            storage: ManuallyDrop::new(storage),
+        };
+        let ScopeGuard { world, storage } = &mut guard;
+
+        f(world, storage.as_mut())
+    }
+
+    pub fn contains_resource<T: Resource>(&self) -> bool {
+        self.resource_allocator().contains::<T>()
+    }
+
+    /// Borrows the resource of type `T`. Returns `None` if the world
+    /// contains no such resource.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is currently borrowed mutably.
+    pub fn get_resource<T: Resource>(&self) -> Option<ReadResource<'_, T>> {
+        self.resource_allocator()
+            .get::<T>()
+            .map(|storage| ReadResource::new(storage, 0))
+    }
+
+    /// Mutably borrows the resource of type `T`, which is only marked as
+    /// changed once it is dereferenced mutably. Returns `None` if the
+    /// world contains no such resource.
+    pub fn get_resource_mut<T: Resource>(&mut self) -> Option<Mut<'_, T>> {
+        self.resource_storage_mut()
+            .get_exclusive::<T>()
+            .map(ResourceStorage::as_mut)
+    }
+
+    /// Inserts a non-Send resource into the world, replacing and returning
+    /// the resource of the same type if there already was one. Non-Send
+    /// resources can only be accessed on the thread which owns the world,
+    /// which is the thread its dispatcher was created on, or otherwise
+    /// the thread which inserted its first non-Send resource. A world
+    /// dropped on any other thread leaks its non-Send resources.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the one which owns the
+    /// world.
+    pub fn insert_non_send<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.resource_storage_mut().non_send_mut().insert(resource)
+    }
+
+    /// Removes the non-Send resource of type `T` from the world and
+    /// returns it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the one which owns the
+    /// world.
+    pub fn remove_non_send<T: 'static>(&mut self) -> Option<T> {
+        self.resource_storage_mut().non_send_mut().remove::<T>()
+    }
+
+    pub fn contains_non_send<T: 'static>(&self) -> bool {
+        self.resource_allocator().non_send().contains::<T>()
+    }
+
+    /// Borrows the non-Send resource of type `T`. Returns `None` if the
+    /// world contains no such resource.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is currently borrowed mutably, or if called
+    /// from any thread but the one which owns the world.
+    pub fn get_non_send<T: 'static>(&self) -> Option<NonSend<'_, T>> {
+        let storage = self.resource_allocator().non_send();
+
+        if !storage.contains::<T>() {
+            return None;
+        }
+
+        let resource = storage
+            .try_get::<T>()
+            .expect("Non-Send resource is already borrowed mutably.");
+
+        Some(resource)
+    }
+
+    /// Mutably borrows the non-Send resource of type `T`. Returns `None`
+    /// if the world contains no such resource.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from any thread but the one which owns the
+    /// world.
+    pub fn get_non_send_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.resource_storage_mut()
+            .non_send_mut()
+            .get_exclusive::<T>()
+    }
+
+    /// Registers an observer which runs whenever an event of type `E` is
+    /// triggered on any entity. Returns an error if the observer requests
+    /// conflicting borrows of the same resource or component type.
+    pub fn add_observer<E, O>(&mut self, observer: O) -> Result<(), AccessError>
+    where
+        E: 'static,
+        O: for<'a> Observer<'a, E> + Send + Sync + 'static,
+    {
+        self.observers.add(None, observer)
+    }
+
+    /// Registers an observer which runs whenever an event of type `E` is
+    /// triggered on `entity`, or propagates to it. Returns an error if the
+    /// observer requests conflicting borrows of the same resource or
+    /// component type.
+    pub fn observe<E, O>(&mut self, entity: Entity, observer: O) -> Result<(), AccessError>
+    where
+        E: 'static,
+        O: for<'a> Observer<'a, E> + Send + Sync + 'static,
+    {
+        self.observers.add(Some(entity), observer)
+    }
+
+    /// Triggers `event` on `entity`, immediately running the observers of
+    /// the entity followed by the global observers of the event type.
+    /// The event only moves on to the entity's [`Parent`] if an observer
+    /// enables propagation with [`Trigger::propagate`].
+    ///
+    /// Returns the first error encountered while fetching the data of an
+    /// observer, but always runs every observer. Observers of `E` can't
+    /// see events of type `E` triggered while they are running.
+    ///
+    /// [`Trigger::propagate`]: crate::observer::Trigger::propagate
+    pub fn trigger<E: 'static>(&mut self, event: E, entity: Entity) -> Result<(), RetrievalError> {
+        Observers::trigger(self, event, entity, false)
+    }
+
+    /// Like [`trigger`](Self::trigger), but the event moves up the parent
+    /// chain of `entity` until an observer stops it, or it reaches an
+    /// entity without a [`Parent`].
+    pub fn trigger_propagating<E: 'static>(
+        &mut self,
+        event: E,
+        entity: Entity,
+    ) -> Result<(), RetrievalError> {
+        Observers::trigger(self, event, entity, true)
+    }
+
+    pub fn parent_of(&self, entity: Entity) -> Option<Entity> {
+        let allocator = self.component_allocator();
+        let storage = allocator.get::<Parent>()?;
+
+        storage.get(entity.id()).map(Parent::get)
     }
 
     pub fn archetype_of(&self, entity: Entity) -> Option<&Archetype> {
-        self.archetypes.iter().find(|arch| arch.contains(entity))
+        let id = *self.locations.get(&entity.id())?;
+        self.archetypes.get(id)
     }
 
     pub fn archetype_of_mut(&mut self, entity: Entity) -> Option<&mut Archetype> {
-        self.archetypes
-            .iter_mut()
-            .find(|arch| arch.contains(entity))
+        let id = *self.locations.get(&entity.id())?;
+        self.archetypes.get_mut(id)
     }
 
     pub fn entity_iter(&self) -> impl Iterator<Item = Entity> + '_ {
@@ -157,34 +769,93 @@ impl World {
         self.archetypes.iter()
     }
 
+    /// The component types seen by the world, along with the info of
+    /// every type which had its storage registered.
+    pub fn components(&self) -> &ComponentRegistry {
+        self.component_allocator().registry()
+    }
+
+    /// Returns the id of `T`, or `None` if the world hasn't seen it yet.
+    pub fn component_id<T: Component>(&self) -> Option<ComponentId> {
+        self.components().get::<T>()
+    }
+
+    /// Returns the info of `T`, or `None` if its storage was never
+    /// registered.
+    pub fn component_info<T: Component>(&self) -> Option<&ComponentInfo> {
+        self.components().info_of::<T>()
+    }
+
     pub(crate) fn resource_storage(&self) -> &RwLock<ResourceStorageAllocator> {
         &self.resource_storage
     }
 
+    fn resource_allocator(&self) -> &ResourceStorageAllocator {
+        // SAFETY: The allocator is only ever locked for writing through
+        // `&mut World`, so it can't be written to while `self` is borrowed.
+        // Systems only ever read-lock it, just like this.
+        unsafe { &*self.resource_storage.data_ptr() }
+    }
+
+    pub(crate) fn resource_storage_mut(&mut self) -> &mut ResourceStorageAllocator {
+        // Resources added or changed through `&mut World` are recorded at
+        // the world's current change tick.
+        let change_tick = *self.change_tick.get_mut();
+        let allocator = self.resource_storage.get_mut();
+        allocator.set_change_tick(change_tick);
+        allocator
+    }
+
     pub(crate) fn component_storage(&self) -> &RwLock<ComponentStorageAllocator> {
         &self.component_storage
     }
 
+    fn component_allocator(&self) -> &ComponentStorageAllocator {
+        // SAFETY: See `resource_allocator`.
+        unsafe { &*self.component_storage.data_ptr() }
+    }
+
+    pub(crate) fn observers_mut(&mut self) -> &mut Observers {
+        &mut self.observers
+    }
+
     fn get_archetype(&self, components: &ComponentSet) -> Option<&Archetype> {
-        self.archetypes
-            .iter()
-            .find(|arch| arch.components() == components)
+        let id = *self.archetype_ids.get(components)?;
+        self.archetypes.get(id)
     }
 
     fn get_archetype_mut(&mut self, components: &ComponentSet) -> Option<&mut Archetype> {
-        self.archetypes
-            .iter_mut()
-            .find(|arch| arch.components() == components)
+        let id = *self.archetype_ids.get(components)?;
+        self.archetypes.get_mut(id)
+    }
+
+    /// Returns the id of the archetype with the components, creating it
+    /// if it doesn't exist yet.
+    fn archetype_id(&mut self, components: ComponentSet) -> ArchetypeId {
+        match self.archetype_ids.get(&components) {
+            Some(&id) => id,
+            None => self.create_archetype(components).id(),
+        }
     }
 
-    fn create_archetype<CT: ComponentTuple>(&mut self, components: ComponentSet) -> &mut Archetype {
+    /// Moves the entity from the archetype `source` to `target`, without
+    /// touching its components.
+    fn move_archetype(&mut self, entity: Entity, source: ArchetypeId, target: ArchetypeId) {
+        self.archetypes[source].remove(entity);
+        self.archetypes[target].push(entity);
+        self.locations.insert(entity.id(), target);
+    }
+
+    fn create_archetype(&mut self, components: ComponentSet) -> &mut Archetype {
         debug_assert!(
             self.get_archetype(&components).is_none(),
             "This method should only be called if the archetype didn't already exist.\
              While this is not unsafe, it is a waste of memory.",
         );
 
-        self.archetypes.push(Archetype::new(components));
+        let id = self.archetypes.len();
+        self.archetype_ids.insert(components.clone(), id);
+        self.archetypes.push(Archetype::new(id, components));
         self.archetypes.last_mut().unwrap_or_else(|| unsafe {
             utils::debug_unreachable(
                 "self.archetypes did not contain last element after it was pushed to.",
@@ -192,3 +863,160 @@ impl World {
         })
     }
 }
+
+/// Returns the resource taken out by [`World::resource_scope`] when the
+/// scope ends, including when it ends by unwinding.
+struct ScopeGuard<'w, T: Resource> {
+    world: &'w mut World,
+    storage: ManuallyDrop<ResourceStorage<T>>,
+}
+
+impl<T: Resource> Drop for ScopeGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: the storage is never used again after it is taken.
+        let storage = unsafe { ManuallyDrop::take(&mut self.storage) };
+        self.world.resource_storage_mut().end_scope(storage);
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use crate::change::ChangeTicks;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct A;
+    #[derive(Debug, Default)]
+    struct B;
+    #[derive(Debug)]
+    struct C;
+
+    #[test]
+    fn required_only_from_added_components() {
+        let mut world = World::new();
+        let entity = world.create_entity(A);
+
+        // The entity had `A` before `A` required `B`, so adding an
+        // unrelated component doesn't add `B` to it.
+        world.register_required::<A, B>();
+        world.add_components(entity, C).unwrap();
+
+        let b = world.component_id::<B>().unwrap();
+        let c = world.component_id::<C>().unwrap();
+        let components = world.archetype_of(entity).unwrap().components();
+        assert!(components.contains(c));
+        assert!(!components.contains(b));
+
+        // Entities which gain `A` get `B` with it.
+        let other = world.create_entity(C);
+        world.add_components(other, A).unwrap();
+        assert!(world.archetype_of(other).unwrap().components().contains(b));
+    }
+
+    #[test]
+    fn remove_dynamic_at_change_tick() {
+        let mut world = World::new();
+        let id = world.register_dynamic("u32", Layout::new::<u32>(), None);
+        let entity = world.spawn().build();
+        assert!(unsafe { world.insert_dynamic(entity, id, &7u32.to_ne_bytes()) });
+
+        world.increment_change_tick();
+        assert!(world.remove_dynamic(entity, id));
+        assert!(!world.remove_dynamic(entity, id));
+        assert!(world.get_dynamic(entity, id).is_none());
+
+        let allocator = world.component_allocator();
+        let storage = allocator.get_dynamic(id).unwrap();
+        assert_eq!(storage.change_tick(), world.change_tick());
+    }
+
+    #[test]
+    fn insert_get_and_remove_resources() {
+        let mut world = World::new();
+        assert!(world.get_resource::<u32>().is_none());
+        assert!(world.get_resource_mut::<u32>().is_none());
+        assert_eq!(world.remove_resource::<u32>(), None);
+
+        assert_eq!(world.insert_resource(1u32), None);
+        assert_eq!(world.insert_resource(2u32), Some(1));
+        assert_eq!(**world.get_resource::<u32>().unwrap(), 2);
+
+        *world.get_resource_mut::<u32>().unwrap() += 1;
+        assert_eq!(**world.get_resource::<u32>().unwrap(), 3);
+
+        // Resources of other types are unaffected.
+        world.insert_resource(4u64);
+        assert_eq!(world.remove_resource::<u32>(), Some(3));
+        assert!(world.get_resource::<u32>().is_none());
+        assert_eq!(**world.get_resource::<u64>().unwrap(), 4);
+    }
+
+    #[test]
+    fn resource_scope_survives_panic() {
+        let mut world = World::new();
+        world.insert_resource(1u32);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            world.resource_scope(|_, mut value: Mut<u32>| {
+                *value = 2;
+                panic!("scope failed");
+            })
+        }));
+        assert!(result.is_err());
+
+        // The resource was put back, and is no longer scoped.
+        assert_eq!(**world.get_resource::<u32>().unwrap(), 2);
+        world.resource_scope(|_, mut value: Mut<u32>| *value += 1);
+        assert_eq!(**world.get_resource::<u32>().unwrap(), 3);
+    }
+
+    #[test]
+    fn resource_scope_change_ticks() {
+        let mut world = World::new();
+        world.insert_resource(1u32);
+        let added = world.change_tick();
+        let changed = |world: &World| world.get_resource::<u32>().unwrap().ticks().changed();
+
+        world.increment_change_tick();
+        world.resource_scope(|world, value: Mut<u32>| {
+            assert!(!world.contains_resource::<u32>());
+            assert_eq!(*value, 1);
+        });
+        assert!(world.contains_resource::<u32>());
+        assert_eq!(changed(&world), added);
+
+        world.resource_scope(|_, mut value: Mut<u32>| *value = 2);
+        assert_eq!(changed(&world), world.change_tick());
+    }
+
+    #[test]
+    fn resource_change_ticks() {
+        let mut world = World::new();
+        world.insert_resource(1u32);
+        let added = world.change_tick();
+        let ticks = |world: &World| world.get_resource::<u32>().unwrap().ticks();
+
+        // Only a mutable dereference marks the resource as changed.
+        world.increment_change_tick();
+        assert_eq!(*world.get_resource_mut::<u32>().unwrap(), 1);
+        assert_eq!(ticks(&world).changed(), added);
+
+        *world.get_resource_mut::<u32>().unwrap() = 2;
+        assert_eq!(ticks(&world).changed(), world.change_tick());
+        assert_eq!(ticks(&world).added(), added);
+
+        // A replacing resource is added anew.
+        world.increment_change_tick();
+        assert_eq!(world.insert_resource(3u32), Some(2));
+        assert_eq!(ticks(&world), ChangeTicks::new(world.change_tick()));
+    }
+}
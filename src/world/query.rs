@@ -1,6 +1,11 @@
-use std::{marker::PhantomData, mem, ops::Deref};
+use std::{
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::{Deref, DerefMut},
+};
 
 use crate::{
+    change::SystemTicks,
     system::{ComponentData, ResourceData, RetrievalError, System},
     world::World,
 };
@@ -12,6 +17,7 @@ pub struct Query<'a, R: ResourceData<'a>, C: ComponentData<'a>> {
 }
 
 impl<'a, R: ResourceData<'a>, C: ComponentData<'a>> Query<'a, R, C> {
+    #[allow(clippy::self_named_constructors)]
     pub fn query(world: &'a World) -> Self {
         Self {
             world,
@@ -42,7 +48,10 @@ impl<'a, R: ResourceData<'a>, C: ComponentData<'a>> Query<'a, R, C> {
 #[derive(Debug)]
 pub struct QueryResources<'a, R: ResourceData<'a>> {
     world: &'a World,
-    resources: R,
+    // Borrows from `state`, so it is dropped manually before `state`
+    // is freed.
+    resources: ManuallyDrop<R>,
+    state: *mut R::State,
 }
 
 impl<'a, R: ResourceData<'a>> QueryResources<'a, R> {
@@ -55,15 +64,27 @@ impl<'a, R: ResourceData<'a>> QueryResources<'a, R> {
         mem::forget(guard);
 
         let allocator = &*ptr;
-        let resources = R::fetch(allocator).map_err(|e| {
+
+        // The state lives on the heap, so the resources can keep
+        // borrowing it for as long as Self is alive.
+        let state = Box::into_raw(Box::new(R::init_state()));
+
+        // Queries have no previous run, so every resource counts as
+        // added and changed.
+        let ticks = SystemTicks::new(0, world.increment_change_tick());
+        let resources = R::fetch(allocator, &mut *state, ticks).inspect_err(|_| {
             // If an error is returned, the RwLock needs to
             // be unlocked, else it would just be read locked
             // forever.
+            drop(Box::from_raw(state));
             world.resource_storage().force_unlock_read();
-            e
         })?;
 
-        Ok(Self { world, resources })
+        Ok(Self {
+            world,
+            resources: ManuallyDrop::new(resources),
+            state,
+        })
     }
 }
 
@@ -75,9 +96,17 @@ impl<'a, R: ResourceData<'a>> Deref for QueryResources<'a, R> {
     }
 }
 
+impl<'a, R: ResourceData<'a>> DerefMut for QueryResources<'a, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        &mut self.resources
+    }
+}
+
 impl<'a, R: ResourceData<'a>> Drop for QueryResources<'a, R> {
     fn drop(&mut self) {
         unsafe {
+            ManuallyDrop::drop(&mut self.resources);
+            drop(Box::from_raw(self.state));
             self.world.resource_storage().force_unlock_read();
         }
     }
@@ -97,6 +126,12 @@ impl<'a, C: ComponentData<'a>> Deref for QueryComponents<'a, C> {
     }
 }
 
+impl<'a, C: ComponentData<'a>> DerefMut for QueryComponents<'a, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.components
+    }
+}
+
 impl<'a, C: ComponentData<'a>> QueryComponents<'a, C> {
     unsafe fn new(world: &'a World) -> Result<Self, RetrievalError> {
         // Acquire a read lock on the component allocator
@@ -107,12 +142,14 @@ impl<'a, C: ComponentData<'a>> QueryComponents<'a, C> {
         mem::forget(guard);
 
         let allocator = &*ptr;
-        let components = C::fetch(allocator).map_err(|e| {
+        // Queries have no previous run, so every component counts as
+        // added and changed.
+        let ticks = SystemTicks::new(0, world.increment_change_tick());
+        let components = C::fetch(allocator, ticks).inspect_err(|_| {
             // If an error is returned, the RwLock needs to
             // be unlocked, else it would just be read locked
             // forever.
             world.component_storage().force_unlock_read();
-            e
         })?;
 
         Ok(Self { world, components })